@@ -0,0 +1,298 @@
+-- Turtle client, served by the turtles server at /client.lua.
+--
+-- Connects to the server over a websocket, runs every action it sends and
+-- answers with the turtle's state. The JSON shapes mirror `Action`,
+-- `ActionType`, `Handshake` and `InfoResp` in the `models` crate.
+
+local SERVER_URL = "{{SERVER_URL}}"
+local PROTOCOL_VERSION = {{PROTOCOL_VERSION}}
+local RECONNECT_DELAY = 5
+local GPS_TIMEOUT = 2
+
+-- GPS is only asked at startup and to learn the heading from the first
+-- step sideways, after that moves and turns are tracked here
+local position = nil
+local heading = nil
+
+local headings = {
+    { x = 0, z = -1 }, -- north
+    { x = 1, z = 0 }, -- east
+    { x = 0, z = 1 }, -- south
+    { x = -1, z = 0 }, -- west
+}
+
+local function locate()
+    local x, y, z = gps.locate(GPS_TIMEOUT)
+    if not x then
+        return nil
+    end
+    return { x = x, y = y, z = z }
+end
+
+local function turned(dir)
+    if heading then
+        heading = (heading + (dir == "Right" and 1 or -1) - 1) % 4 + 1
+    end
+end
+
+local function moved(dir)
+    if dir == "Up" or dir == "Down" then
+        position.y = position.y + (dir == "Up" and 1 or -1)
+        return
+    end
+
+    local sign = dir == "Forward" and 1 or -1
+    if heading then
+        position.x = position.x + sign * headings[heading].x
+        position.z = position.z + sign * headings[heading].z
+        return
+    end
+
+    local now = locate()
+    if not now then
+        -- where the step went is unknown, so take it back: the server then
+        -- sees the move fail instead of a stale position it would retry from
+        local back = dir == "Forward" and turtle.back or turtle.forward
+        if not back() then
+            position = nil
+            error("lost track of the position, no GPS fix", 0)
+        end
+        print("no GPS fix, heading still unknown")
+        return
+    end
+    for i, delta in ipairs(headings) do
+        if now.x - position.x == sign * delta.x and now.z - position.z == sign * delta.z then
+            heading = i
+        end
+    end
+    position = now
+end
+
+-- textutils writes empty tables as objects, serde wants arrays
+local function array(t)
+    if next(t) == nil then
+        return textutils.empty_json_array
+    end
+    return t
+end
+
+-- tag sets come as { ["minecraft:logs"] = true }, serde wants a list
+local function keys(t)
+    local result = {}
+    for k in pairs(t or {}) do
+        result[#result + 1] = k
+    end
+    return array(result)
+end
+
+local function inspect(direction, fn)
+    local exists, data = fn()
+    return {
+        direction = direction,
+        exists = exists,
+        block = exists and data.name or nil,
+        state = exists and data.state or nil,
+    }
+end
+
+local function slots()
+    local result = {}
+    for i = 1, 16 do
+        local item = turtle.getItemDetail(i)
+        result[i] = {
+            id = i,
+            type = item and { name = item.name, count = item.count } or nil,
+            space = turtle.getItemSpace(i),
+        }
+    end
+    return result
+end
+
+-- the chest the turtle stands on, if any
+local function chest()
+    local below = peripheral.wrap("bottom")
+    if not below or not below.list then
+        return nil
+    end
+
+    local items = {}
+    for _, item in pairs(below.list()) do
+        items[#items + 1] = { name = item.name, count = item.count, nbt = item.nbt }
+    end
+    return { slots = array(items), size = below.size() }
+end
+
+local function info(id, result)
+    local fuel = turtle.getFuelLevel()
+    if fuel == "unlimited" then
+        -- fuel disabled in the server config, never ask for a refuel
+        fuel = 1000000
+    end
+
+    return {
+        id = id,
+        chest = chest(),
+        fuel = fuel,
+        slots = slots(),
+        blocks = {
+            inspect("Forward", turtle.inspect),
+            inspect("Up", turtle.inspectUp),
+            inspect("Down", turtle.inspectDown),
+        },
+        pos = position,
+        result = result,
+    }
+end
+
+local moves = {
+    Forward = turtle.forward,
+    Backward = turtle.back,
+    Up = turtle.up,
+    Down = turtle.down,
+}
+local turns = { Left = turtle.turnLeft, Right = turtle.turnRight }
+local digs = { Forward = turtle.dig, Up = turtle.digUp, Down = turtle.digDown }
+local chests = { Deposit = turtle.dropDown, Withdraw = turtle.suckDown }
+local suffixes = { Forward = "", Up = "Up", Down = "Down" }
+
+-- calls e.g. turtle.placeUp for ("place", "Up")
+local function call(name, dir, ...)
+    return turtle[name .. suffixes[dir]](...)
+end
+
+local function outcome(ok, err)
+    return { Outcome = { ok = ok, error = err } }
+end
+
+local handlers = {
+    Move = function(dir)
+        if moves[dir]() then
+            moved(dir)
+        end
+    end,
+    Turn = function(dir)
+        if turns[dir]() then
+            turned(dir)
+        end
+    end,
+    Mine = function(dir) digs[dir]() end,
+    Refuel = function(a) turtle.refuel(a.count) end,
+    Interact = function() end,
+    Chest = function(action) chests[action]() end,
+    -- `{ name = "Select", args = { 1 } }` calls turtle.select(1)
+    Slot = function(slot)
+        local fn = turtle[slot.name:sub(1, 1):lower() .. slot.name:sub(2)]
+        if fn then
+            fn(table.unpack(slot.args))
+        else
+            print("unknown slot action " .. slot.name)
+        end
+    end,
+    Select = function(slot) turtle.select(slot) end,
+    Info = function() end,
+    Place = function(dir) return outcome(call("place", dir)) end,
+    Drop = function(a) return outcome(call("drop", a.direction, a.count)) end,
+    Suck = function(a) return outcome(call("suck", a.direction, a.count)) end,
+    Inspect = function(dir)
+        local ok, data = call("inspect", dir)
+        if not ok then
+            return { Block = textutils.json_null }
+        end
+        return { Block = { name = data.name, state = data.state, tags = keys(data.tags) } }
+    end,
+    Compare = function(dir) return outcome(call("compare", dir)) end,
+    Attack = function(dir) return outcome(call("attack", dir)) end,
+    TransferTo = function(a) return outcome(turtle.transferTo(a.slot, a.count)) end,
+    GetItemDetail = function(a)
+        local item = turtle.getItemDetail(a.slot, a.detailed)
+        if not item then
+            return { Item = textutils.json_null }
+        end
+        return {
+            Item = {
+                name = item.name,
+                count = item.count,
+                nbt = item.nbt,
+                displayName = item.displayName,
+                damage = item.damage,
+                maxDamage = item.maxDamage,
+                tags = keys(item.tags),
+            },
+        }
+    end,
+    Equip = function(side) return outcome(turtle["equip" .. side]()) end,
+    Craft = function(limit)
+        if not turtle.craft then
+            return outcome(false, "No crafting table equipped")
+        end
+        return outcome(turtle.craft(limit))
+    end,
+}
+
+local function run(action)
+    -- unit variants arrive as plain strings, the rest as { Variant = value }
+    local name, arg = action, nil
+    if type(action) == "table" then
+        name, arg = next(action)
+    end
+
+    local handler = handlers[name]
+    if not handler then
+        print("unknown action " .. tostring(name))
+        return nil
+    end
+    return handler(arg)
+end
+
+local function session(ws)
+    if not position then
+        position = locate()
+    end
+    if not position then
+        -- a made up position would send the server's map astray
+        error("no GPS fix, is a GPS host in range?", 0)
+    end
+
+    ws.send(textutils.serializeJSON({
+        id = os.getComputerID(),
+        label = os.getComputerLabel(),
+        kind = term.isColour() and "Advanced" or "Normal",
+        version = PROTOCOL_VERSION,
+    }))
+
+    while true do
+        local message = ws.receive()
+        if not message then
+            return
+        end
+
+        local action = textutils.unserializeJSON(message)
+        if action and action.reason then
+            -- the server refused this client, reconnecting will not help
+            error({ rejected = action.reason }, 0)
+        elseif action and action.id then
+            local result = run(action.action)
+            ws.send(textutils.serializeJSON(info(action.id, result)))
+        end
+    end
+end
+
+print("turtle client, protocol v" .. PROTOCOL_VERSION)
+position = locate()
+while true do
+    print("connecting to " .. SERVER_URL)
+    local ws, err = http.websocket(SERVER_URL)
+    if ws then
+        local ok, e = pcall(session, ws)
+        pcall(ws.close)
+        if not ok and type(e) == "table" and e.rejected then
+            printError(e.rejected)
+            return
+        elseif not ok then
+            print("session failed: " .. tostring(e))
+        end
+    else
+        print("connect failed: " .. tostring(err))
+    end
+    sleep(RECONNECT_DELAY)
+end
@@ -0,0 +1,18 @@
+-- Installs the turtle client as the startup program and runs it:
+--
+--     wget run {{HTTP_URL}}/install.lua
+
+local CLIENT_URL = "{{HTTP_URL}}/client.lua"
+
+local response, err = http.get(CLIENT_URL)
+if not response then
+    error("could not download " .. CLIENT_URL .. ": " .. tostring(err))
+end
+
+local file = fs.open("startup.lua", "w")
+file.write(response.readAll())
+file.close()
+response.close()
+
+print("installed client as startup.lua")
+shell.run("startup.lua")
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS turtles (
+    id BIGINT PRIMARY KEY,
+    label TEXT,
+    kind JSONB NOT NULL,
+    x BIGINT NOT NULL,
+    y BIGINT NOT NULL,
+    z BIGINT NOT NULL,
+    direction JSONB NOT NULL,
+    fuel BIGINT NOT NULL,
+    curr_goal JSONB NOT NULL,
+    main_goal JSONB NOT NULL,
+    prev_x BIGINT NOT NULL,
+    prev_y BIGINT NOT NULL,
+    prev_z BIGINT NOT NULL,
+    prev_direction JSONB NOT NULL,
+    action_queue JSONB NOT NULL,
+    executed_actions JSONB NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT now()
+);
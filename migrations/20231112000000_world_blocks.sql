@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS world_blocks (
+    x BIGINT NOT NULL,
+    y BIGINT NOT NULL,
+    z BIGINT NOT NULL,
+    -- NULL when the cell was seen but the block could not be identified
+    block TEXT,
+    observed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (x, y, z)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS jobs (
+    id BIGINT PRIMARY KEY,
+    owner BIGINT NOT NULL,
+    kind JSONB NOT NULL,
+    status JSONB NOT NULL,
+    done BIGINT NOT NULL,
+    total BIGINT NOT NULL,
+    checkpoint JSONB NOT NULL,
+    message TEXT
+);
@@ -0,0 +1,3 @@
+ALTER TABLE jobs ALTER COLUMN owner DROP NOT NULL;
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS group_id BIGINT;
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
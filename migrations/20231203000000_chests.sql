@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS chests (
+    x BIGINT NOT NULL,
+    y BIGINT NOT NULL,
+    z BIGINT NOT NULL,
+    -- the Chest a turtle last read, as JSON
+    contents JSONB NOT NULL,
+    seen_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (x, y, z)
+);
@@ -0,0 +1 @@
+ALTER TABLE turtles ADD COLUMN IF NOT EXISTS heading_known BOOLEAN NOT NULL DEFAULT FALSE;
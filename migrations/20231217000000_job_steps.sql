@@ -0,0 +1,2 @@
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS step BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE jobs ALTER COLUMN checkpoint DROP NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS skipped BIGINT NOT NULL DEFAULT 0;
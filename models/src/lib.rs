@@ -1,15 +1,62 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Copy, Eq, Hash)]
+/// Version of the JSON protocol spoken with the turtle client.
+///
+/// 1. The original format, also assumed for clients that send no version.
+///    Replies carry no `InfoResp::id` and are matched to actions by order.
+/// 2. Adds `ActionType::Select`, which version 1 spells as
+///    `Slot { name: "Select", args: [slot] }`.
+/// 3. Adds the rest of the turtle API, from `Place` to `Craft`, and
+///    `InfoResp::result`. Older clients get the actions they can run as
+///    `Slot` actions but send no results.
+/// 4. `ActionType::Refuel` takes a count, which older clients get as
+///    `Slot { name: "Refuel", args: [count] }`.
+pub const PROTOCOL_VERSION: u32 = 4;
+/// Oldest client version the server still talks to.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(
+    Default, Debug, Clone, PartialEq, Serialize, Deserialize, Copy, Eq, Hash, PartialOrd, Ord,
+)]
 pub struct Position {
     pub x: i64,
     pub y: i64,
     pub z: i64,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+impl Position {
+    pub fn offset(&self, dx: i64, dy: i64, dz: i64) -> Position {
+        Position {
+            x: self.x + dx,
+            y: self.y + dy,
+            z: self.z + dz,
+        }
+    }
+
+    /// The neighbouring position one block towards `dir`.
+    pub fn step(&self, dir: Direction) -> Position {
+        match dir {
+            Direction::North => self.offset(0, 0, -1),
+            Direction::South => self.offset(0, 0, 1),
+            Direction::East => self.offset(1, 0, 0),
+            Direction::West => self.offset(-1, 0, 0),
+        }
+    }
+
+    /// The position a turtle at `self` facing `facing` sees in `dir`.
+    pub fn looking(&self, facing: Direction, dir: MineDirection) -> Position {
+        match dir {
+            MineDirection::Forward => self.step(facing),
+            MineDirection::Up => self.offset(0, 1, 0),
+            MineDirection::Down => self.offset(0, -1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Direction {
     North,
     South,
@@ -23,6 +70,30 @@ pub struct Block {
     pub direction: MineDirection,
     pub exists: bool,
     pub block: Option<String>,
+    /// Block state properties, such as a crop's `age`. Empty for air and
+    /// for clients that do not report them.
+    #[serde(default)]
+    pub state: HashMap<String, serde_json::Value>,
+}
+
+impl Direction {
+    pub fn left(&self) -> Direction {
+        match self {
+            Direction::North => Direction::West,
+            Direction::West => Direction::South,
+            Direction::South => Direction::East,
+            Direction::East => Direction::North,
+        }
+    }
+
+    pub fn right(&self) -> Direction {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Copy)]
@@ -31,12 +102,32 @@ pub enum Material {
     Diamond = -53,
 }
 
+impl Material {
+    /// The y-level the material is most common at.
+    pub fn level(&self) -> i64 {
+        *self as i64
+    }
+
+    /// How the ore block ids of the material end, such as `diamond_ore` in
+    /// `minecraft:deepslate_diamond_ore`.
+    pub fn ore_suffix(&self) -> &'static str {
+        match self {
+            Material::Coal => "coal_ore",
+            Material::Diamond => "diamond_ore",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Copy)]
 pub enum Goal {
     Mine(Material),
     Refuel,
     Deposit,
     Idle,
+    /// Patrol the configured tree farm, chopping grown trees and replanting.
+    TreeFarm,
+    /// Patrol the configured crop plot, harvesting ripe crops and replanting.
+    Farm,
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone, Copy)]
@@ -87,9 +178,203 @@ pub enum QueuedAction {
     MovePoint(Position),
     Turn(TurnDirection),
     TurnToward(Direction),
+    /// Dig without moving.
+    Mine(MineDirection),
+    /// Look around for ore and mine out any vein that is found.
+    InspectWalls,
+    /// Dig out every block between two corners.
+    Quarry {
+        a: Position,
+        b: Position,
+        order: QuarryOrder,
+    },
+    /// Move into `pos`, then dig the blocks above and/or below it.
+    DigColumn {
+        pos: Position,
+        up: bool,
+        down: bool,
+    },
+    /// Go to the deposit chest and empty the inventory into it.
+    Deposit,
+    /// Collect `count` of `item` from the chests known to hold it and drop
+    /// them into the chest at `destination`.
+    Fetch {
+        item: String,
+        count: i64,
+        destination: Position,
+    },
+    /// Chop the tree growing at `spot` if there is one and make sure a
+    /// `sapling` is planted there.
+    TendTree { spot: Position, sapling: String },
+    /// Burn fuel items from the inventory up to the refuel target, if the
+    /// fuel level is below the refuel threshold.
+    BurnFuel,
+    /// Empty the inventory into `chest`, keeping up to the given count of
+    /// each item in `keep`.
+    Store {
+        chest: Position,
+        keep: Vec<(String, i64)>,
+    },
+    /// Harvest the crop at `pos` if it is ripe and replant it, planting
+    /// `seed` if nothing grows there.
+    TendCrop { pos: Position, seed: String },
+    /// Do nothing for this many seconds.
+    Wait(u64),
+    /// Top the inventory up to these item counts from storage.
+    Restock(Vec<(String, i64)>),
+    /// Place `item` at `pos` from the cell above it.
+    PlaceBlock { pos: Position, item: String },
+    /// Switch the turtle to a new goal, dropping whatever is still queued.
+    SetGoal(Goal),
     Nothing,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum QuarryOrder {
+    TopDown,
+    BottomUp,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Progress {
+    pub done: u64,
+    pub total: u64,
+    /// Blocks left standing because they could not be reached.
+    #[serde(default)]
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Paused,
+    Failed,
+    Cancelled,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum JobKind {
+    Quarry {
+        a: Position,
+        b: Position,
+        order: QuarryOrder,
+    },
+    /// Go home and empty the inventory into the deposit chest.
+    Deposit,
+    /// Bring `count` of `item` from storage to the chest at `destination`.
+    Fetch {
+        item: String,
+        count: i64,
+        destination: Position,
+    },
+    /// Build `schematic` with its origin corner at `origin`.
+    Build {
+        schematic: Schematic,
+        origin: Position,
+        rotation: Rotation,
+    },
+}
+
+/// A structure to build, as a palette of block names and the blocks that
+/// use them. Block positions are offsets from the structure's origin
+/// corner, with the same axes as the world.
+///
+/// ```json
+/// {
+///   "palette": ["minecraft:cobblestone", "minecraft:oak_planks"],
+///   "blocks": [{ "x": 0, "y": 0, "z": 0, "block": 0 }]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Schematic {
+    pub palette: Vec<String>,
+    pub blocks: Vec<SchematicBlock>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchematicBlock {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    /// Index into the palette.
+    pub block: usize,
+}
+
+/// Clockwise turn around the y axis, seen from above.
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+/// A unit of work owned by one turtle. `checkpoint` holds the actions still
+/// to run, so an interrupted job picks up where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    /// Turtle running the job, `None` while it waits for an idle turtle.
+    pub owner: Option<usize>,
+    /// Set on the parts of a region that was split across turtles.
+    pub group: Option<u64>,
+    /// Times the job failed and was handed to another turtle.
+    pub attempts: u32,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub progress: Progress,
+    pub checkpoint: VecDeque<QueuedAction>,
+    /// Actions of the job's plan done so far. Storage keeps this instead of
+    /// `checkpoint`, which is planned again from `kind` on load.
+    #[serde(default)]
+    pub step: u64,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewJob {
+    pub owner: usize,
+    pub kind: JobKind,
+}
+
+/// A quarry to split into parts, one per idle turtle.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SplitQuarry {
+    pub a: Position,
+    pub b: Position,
+    pub order: QuarryOrder,
+    /// Number of parts, defaults to the number of idle turtles.
+    pub parts: Option<usize>,
+}
+
+/// A schematic to build. Without an owner the scheduler picks the turtle.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildStructure {
+    pub schematic: Schematic,
+    pub origin: Position,
+    #[serde(default)]
+    pub rotation: Rotation,
+    pub owner: Option<usize>,
+}
+
+/// Items to bring from storage. The server picks the turtle.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FetchItems {
+    pub item: String,
+    pub count: i64,
+    /// The chest to drop the items into.
+    pub destination: Position,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OreSighting {
+    pub pos: Position,
+    pub block: String,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Slot {
@@ -108,9 +393,18 @@ pub struct Type {
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Action {
+    /// Request id, echoed back in the matching `InfoResp`.
+    pub id: u64,
     pub action: ActionType,
 }
 
+impl Action {
+    /// Builds an action without a request id; `send_command` assigns one.
+    pub fn new(action: ActionType) -> Self {
+        Self { id: 0, action }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum MineDirection {
     Forward,
@@ -121,6 +415,7 @@ pub enum MineDirection {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum ChestAction {
     Deposit,
+    Withdraw,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -128,18 +423,138 @@ pub enum ActionType {
     Move(MoveDirection),
     Turn(TurnDirection),
     Mine(MineDirection),
-    Refuel,
+    /// Burns `count` of the selected stack, all of it if `None`.
+    Refuel {
+        count: Option<i64>,
+    },
     Interact,
     Chest(ChestAction),
     Slot { name: String, args: Vec<i64> },
+    Select(i64),
     Info,
+    /// Places the selected item.
+    Place(MineDirection),
+    /// Drops `count` of the selected stack, all of it if `None`.
+    Drop {
+        direction: MineDirection,
+        count: Option<i64>,
+    },
+    /// Picks up `count` items, a stack if `None`.
+    Suck {
+        direction: MineDirection,
+        count: Option<i64>,
+    },
+    Inspect(MineDirection),
+    /// Whether the block is the same as the selected item.
+    Compare(MineDirection),
+    Attack(MineDirection),
+    /// Moves items from the selected slot into `slot`.
+    TransferTo { slot: i64, count: Option<i64> },
+    GetItemDetail { slot: Option<i64>, detailed: bool },
+    Equip(Side),
+    /// Crafts with the turtle's inventory, up to `limit` items.
+    Craft(Option<i64>),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Whether an action worked, and ComputerCraft's reason if it did not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outcome {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// A block as `turtle.inspect` reports it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockState {
+    pub name: String,
+    /// Block state properties, such as `age` for crops.
+    #[serde(default)]
+    pub state: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// An item as `turtle.getItemDetail` reports it. The detailed fields are
+/// only filled when asked for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDetail {
+    pub name: String,
+    pub count: i64,
+    pub nbt: Option<String>,
+    pub display_name: Option<String>,
+    pub damage: Option<i64>,
+    pub max_damage: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// What an action returned, sent back in `InfoResp::result`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActionResult {
+    Outcome(Outcome),
+    /// `None` when there is no block.
+    Block(Option<BlockState>),
+    /// `None` for an empty slot.
+    Item(Option<ItemDetail>),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurtleKind {
+    Normal,
+    Advanced,
+}
+
+/// First message a turtle sends after opening the websocket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Handshake {
+    /// ComputerCraft computer id (`os.getComputerID()`), used as the turtle id.
+    pub id: usize,
+    pub label: Option<String>,
+    pub kind: TurtleKind,
+    /// Protocol version of the client, see `PROTOCOL_VERSION`.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+/// Sent to a client whose protocol version is not supported, right before
+/// the server closes the connection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rejected {
+    pub reason: String,
+    pub min_version: u32,
+    pub max_version: u32,
+}
+
+/// Executed actions a turtle remembers, newest last.
+pub const EXECUTED_ACTIONS_KEPT: usize = 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Turtle {
     pub id: usize,
+    pub label: Option<String>,
+    pub kind: TurtleKind,
+    pub online: bool,
+    /// Unix time in seconds of the last response from the turtle.
+    pub last_seen: u64,
     pub pos: Position,
     pub direction: Direction,
+    /// Whether `direction` was found with `reset_north` and tracked since,
+    /// so a reconnecting turtle can skip finding it again.
+    #[serde(default)]
+    pub heading_known: bool,
     pub blocks: Vec<Block>,
     pub curr_goal: Goal,
     pub slots: Vec<Slot>,
@@ -152,9 +567,48 @@ pub struct Turtle {
     pub prev_pos: Position,
     pub prev_dir: Direction,
     pub executed_actions: VecDeque<QueuedAction>,
+    pub ores_found: Vec<OreSighting>,
+    /// Blocks done out of total for the current quarry.
+    pub progress: Option<Progress>,
 }
 
 impl Turtle {
+    pub fn new(handshake: Handshake) -> Self {
+        Self {
+            id: handshake.id,
+            label: handshake.label,
+            kind: handshake.kind,
+            online: false,
+            last_seen: 0,
+            pos: Position { x: 0, y: 0, z: 0 },
+            fuel: 0,
+            slots: vec![],
+            blocks: vec![],
+            visited: HashSet::new(),
+            prev_pos: Position { x: 0, y: 0, z: 0 },
+            prev_dir: Direction::North,
+            direction: Direction::North,
+            heading_known: false,
+            curr_goal: Goal::Idle,
+            main_goal: Goal::Idle,
+            mine_area: vec![],
+            action_list: vec![],
+            action_queue: VecDeque::new(),
+            executed_actions: VecDeque::with_capacity(EXECUTED_ACTIONS_KEPT),
+            ores_found: vec![],
+            progress: None,
+        }
+    }
+
+    /// Adds `action` to the turtle's history, forgetting the oldest once
+    /// there are more than `EXECUTED_ACTIONS_KEPT`.
+    pub fn record_executed(&mut self, action: QueuedAction) {
+        self.executed_actions.push_back(action);
+        while self.executed_actions.len() > EXECUTED_ACTIONS_KEPT {
+            self.executed_actions.pop_front();
+        }
+    }
+
     fn calculate_space(&self) -> f64 {
         let max = self.slots.iter().map(|s| s.space).sum::<i64>() as f64;
         let current = self
@@ -190,8 +644,16 @@ impl Turtle {
 pub enum TurtleError {
     ErrorNoFuel,
     ErrorBlock,
+    ErrorNoPath,
     ErrorWebsockets,
+    ErrorTimeout(u64),
+    ErrorMismatch { expected: u64, got: u64 },
+    ErrorHandshake(String),
     ErrorParsing(String),
+    /// The client's protocol version cannot do what was asked of it.
+    ErrorUnsupported(String),
+    /// Items, with counts, the turtle needs but could not find in storage.
+    ErrorMissingItems(Vec<(String, i64)>),
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -209,12 +671,37 @@ pub struct Slot2 {
     pub nbt: Option<String>,
 }
 
+/// What a chest held the last time a turtle read it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChestContents {
+    /// The chest block itself, not the turtle standing on it.
+    pub pos: Position,
+    pub chest: Chest,
+    /// Unix time in seconds of the last read.
+    pub seen_at: u64,
+}
+
+/// Items of one kind found in one chest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemLocation {
+    pub pos: Position,
+    pub name: String,
+    pub count: i64,
+    pub seen_at: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InfoResp {
+    /// Id of the action this replies to, 0 from version 1 clients.
+    #[serde(default)]
+    pub id: u64,
     pub chest: Option<Chest>,
     pub fuel: i64,
     pub slots: Vec<Slot>,
     pub blocks: Vec<Block>,
     pub pos: Position,
+    /// Result of the action this replies to, for actions that have one.
+    #[serde(default)]
+    pub result: Option<ActionResult>,
 }
@@ -0,0 +1,194 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use models::{
+    BuildStructure, ChestContents, FetchItems, ItemLocation, Job, JobKind, NewJob, Schematic,
+    SplitQuarry,
+};
+use serde::Deserialize;
+
+use crate::jobs::JobError;
+use crate::models::TurtleManager;
+use crate::pathfinding;
+use crate::schematic;
+
+impl IntoResponse for JobError {
+    fn into_response(self) -> Response {
+        match self {
+            JobError::NotFound(id) => {
+                (StatusCode::NOT_FOUND, format!("no job with id {}", id)).into_response()
+            }
+            JobError::InvalidTransition { from, to } => (
+                StatusCode::CONFLICT,
+                format!("cannot move a {:?} job to {:?}", from, to),
+            )
+                .into_response(),
+        }
+    }
+}
+
+pub async fn list_jobs(State(manager): State<Arc<TurtleManager>>) -> Json<Vec<Job>> {
+    Json(manager.jobs.lock().await.list())
+}
+
+pub async fn get_job(
+    State(manager): State<Arc<TurtleManager>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Job>, JobError> {
+    let jobs = manager.jobs.lock().await;
+    jobs.get(id)
+        .cloned()
+        .map(Json)
+        .ok_or(JobError::NotFound(id))
+}
+
+pub async fn create_job(
+    State(manager): State<Arc<TurtleManager>>,
+    Json(new): Json<NewJob>,
+) -> Result<Json<Job>, (StatusCode, String)> {
+    if manager.get_turtle(new.owner).await.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("no turtle with id {}", new.owner),
+        ));
+    }
+
+    Ok(Json(manager.jobs.lock().await.create(
+        Some(new.owner),
+        new.kind,
+        None,
+    )))
+}
+
+/// Splits a quarry into one job per part. The scheduler hands the parts to
+/// idle turtles.
+pub async fn split_quarry(
+    State(manager): State<Arc<TurtleManager>>,
+    Json(split): Json<SplitQuarry>,
+) -> Json<Vec<Job>> {
+    let parts = match split.parts {
+        Some(parts) => parts,
+        None => manager.idle_turtles().await.len(),
+    };
+
+    let jobs = manager
+        .jobs
+        .lock()
+        .await
+        .create_split(split.a, split.b, split.order, parts);
+    manager.schedule().await;
+
+    Json(jobs)
+}
+
+/// Creates a fetch job for the idle turtle closest to the chest holding
+/// most of the item. With no turtle idle, the scheduler hands it to the
+/// next one that is.
+pub async fn fetch_items(
+    State(manager): State<Arc<TurtleManager>>,
+    Json(fetch): Json<FetchItems>,
+) -> Json<Job> {
+    let source = manager
+        .chests
+        .lock()
+        .await
+        .find(&fetch.item)
+        .into_iter()
+        .find(|l| l.name == fetch.item && l.pos != fetch.destination)
+        .map_or(fetch.destination, |l| l.pos);
+
+    let mut idle = Vec::new();
+    for id in manager.idle_turtles().await {
+        if let Some(turtle) = manager.get_turtle(id).await {
+            idle.push((pathfinding::distance(&turtle.lock().await.pos, &source), id));
+        }
+    }
+
+    let kind = JobKind::Fetch {
+        item: fetch.item,
+        count: fetch.count,
+        destination: fetch.destination,
+    };
+    let owner = idle.into_iter().min().map(|(_, id)| id);
+    Json(manager.jobs.lock().await.create(owner, kind, None))
+}
+
+/// Queues a build. The turtle restocks from storage as it goes and the job
+/// pauses, naming what is missing, when storage runs out.
+pub async fn build_structure(
+    State(manager): State<Arc<TurtleManager>>,
+    Json(build): Json<BuildStructure>,
+) -> Result<Json<Job>, (StatusCode, String)> {
+    if let Some(owner) = build.owner {
+        if manager.get_turtle(owner).await.is_none() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("no turtle with id {}", owner),
+            ));
+        }
+    }
+
+    let kind = JobKind::Build {
+        schematic: build.schematic,
+        origin: build.origin,
+        rotation: build.rotation,
+    };
+    let job = manager.jobs.lock().await.create(build.owner, kind, None);
+    manager.schedule().await;
+
+    Ok(Json(job))
+}
+
+/// Items a schematic needs, to check against `/chests/totals` before
+/// building.
+pub async fn schematic_materials(Json(schematic): Json<Schematic>) -> Json<BTreeMap<String, i64>> {
+    Json(schematic::materials(&schematic))
+}
+
+pub async fn pause_job(
+    State(manager): State<Arc<TurtleManager>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Job>, JobError> {
+    manager.jobs.lock().await.pause(id).map(Json)
+}
+
+pub async fn resume_job(
+    State(manager): State<Arc<TurtleManager>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Job>, JobError> {
+    manager.jobs.lock().await.resume(id).map(Json)
+}
+
+pub async fn cancel_job(
+    State(manager): State<Arc<TurtleManager>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Job>, JobError> {
+    manager.jobs.lock().await.cancel(id).map(Json)
+}
+
+pub async fn list_chests(State(manager): State<Arc<TurtleManager>>) -> Json<Vec<ChestContents>> {
+    Json(manager.chests.lock().await.list())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FindItem {
+    item: String,
+}
+
+/// Where items whose name contains `?item=` are stored, most first.
+pub async fn find_item(
+    State(manager): State<Arc<TurtleManager>>,
+    Query(query): Query<FindItem>,
+) -> Json<Vec<ItemLocation>> {
+    Json(manager.chests.lock().await.find(&query.item))
+}
+
+/// How many of each item the known chests hold in total.
+pub async fn item_totals(State(manager): State<Arc<TurtleManager>>) -> Json<BTreeMap<String, i64>> {
+    Json(manager.chests.lock().await.totals())
+}
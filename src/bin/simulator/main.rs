@@ -0,0 +1,212 @@
+//! Simulates turtles against a running server, without Minecraft.
+//!
+//! Each simulated turtle connects to `/ws`, sends its handshake and answers
+//! every action with the `InfoResp` a real turtle would send. All turtles
+//! share one in-memory world, so they see and block each other.
+//!
+//! ```text
+//! cargo run --bin simulator -- [--url ws://127.0.0.1:1337/ws] [--turtles 1]
+//!     [--fuel 1000] [--seed 1] [--delay 0] [--at x,y,z]
+//!     [--fuel-chest x,y,z] [--chest x,y,z]
+//! ```
+//!
+//! Turtles start facing north in a row along x from `--at`, with the
+//! cells they stand in cleared. A dropped connection is retried, keeping
+//! the turtle's state, which makes the simulator handy for testing resume.
+
+use std::{sync::Arc, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use models::{Action, Direction, Handshake, Position, Slot2, TurtleKind, PROTOCOL_VERSION};
+use tokio::sync::Mutex;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+
+use crate::world::{SimTurtle, World, AIR};
+
+mod world;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Turtle ids start here so they do not clash with real computers.
+const FIRST_ID: usize = 1000;
+
+#[derive(Debug)]
+struct Options {
+    url: String,
+    turtles: usize,
+    fuel: i64,
+    seed: u64,
+    delay: Duration,
+    at: Position,
+    fuel_chest: Option<Position>,
+    chests: Vec<Position>,
+}
+
+fn parse_position(text: &str) -> Result<Position, String> {
+    let parts: Vec<i64> = text
+        .split(',')
+        .map(|p| p.trim().parse::<i64>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid position {}: {}", text, e))?;
+
+    match parts[..] {
+        [x, y, z] => Ok(Position { x, y, z }),
+        _ => Err(format!("invalid position {}, expected x,y,z", text)),
+    }
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        url: "ws://127.0.0.1:1337/ws".to_string(),
+        turtles: 1,
+        fuel: 1000,
+        seed: 1,
+        delay: Duration::ZERO,
+        at: Position { x: 0, y: 65, z: 0 },
+        fuel_chest: None,
+        chests: vec![],
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let number = || format!("invalid number for {}: {}", flag, value);
+        match flag.as_str() {
+            "--url" => options.url = value,
+            "--turtles" => options.turtles = value.parse().map_err(|_| number())?,
+            "--fuel" => options.fuel = value.parse().map_err(|_| number())?,
+            "--seed" => options.seed = value.parse().map_err(|_| number())?,
+            "--delay" => {
+                options.delay = Duration::from_millis(value.parse().map_err(|_| number())?)
+            }
+            "--at" => options.at = parse_position(&value)?,
+            "--fuel-chest" => options.fuel_chest = Some(parse_position(&value)?),
+            "--chest" => options.chests.push(parse_position(&value)?),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "simulator=info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut world = World::new(options.seed, options.at.y - 1);
+    if let Some(pos) = options.fuel_chest {
+        let coal = Slot2 {
+            name: "minecraft:coal".to_string(),
+            count: 64,
+            nbt: None,
+        };
+        world.place_chest(pos, vec![coal; 27]);
+    }
+    for pos in &options.chests {
+        world.place_chest(*pos, vec![]);
+    }
+
+    let mut ids = Vec::new();
+    for i in 0..options.turtles {
+        let id = FIRST_ID + i;
+        let pos = options.at.offset(i as i64, 0, 0);
+        world.set(pos, AIR);
+        world.add_turtle(SimTurtle::new(
+            id,
+            TurtleKind::Normal,
+            pos,
+            Direction::North,
+            options.fuel,
+        ));
+        ids.push(id);
+    }
+
+    let world = Arc::new(Mutex::new(world));
+    let options = Arc::new(options);
+    let runs: Vec<_> = ids
+        .into_iter()
+        .map(|id| tokio::spawn(run(id, world.clone(), options.clone())))
+        .collect();
+    for run in runs {
+        let _ = run.await;
+    }
+}
+
+/// Keeps turtle `id` connected, reconnecting whenever the socket drops.
+async fn run(id: usize, world: Arc<Mutex<World>>, options: Arc<Options>) {
+    loop {
+        match connect(id, &world, &options).await {
+            Ok(()) => tracing::info!("turtle {} disconnected", id),
+            Err(e) => tracing::warn!("turtle {} connection failed: {}", id, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn connect(
+    id: usize,
+    world: &Mutex<World>,
+    options: &Options,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let (socket, _) = connect_async(options.url.as_str()).await?;
+    let (mut tx, mut rx) = socket.split();
+
+    let handshake = Handshake {
+        id,
+        label: Some(format!("sim-{}", id)),
+        kind: world
+            .lock()
+            .await
+            .turtle(id)
+            .map_or(TurtleKind::Normal, |t| t.kind),
+        version: PROTOCOL_VERSION,
+    };
+    let handshake = serde_json::to_string(&handshake).expect("handshake serializes");
+    tx.send(Message::Text(handshake)).await?;
+    tracing::info!("turtle {} connected to {}", id, options.url);
+
+    while let Some(msg) = rx.next().await {
+        let text = match msg? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let action: Action = match serde_json::from_str(&text) {
+            Ok(action) => action,
+            Err(e) => {
+                tracing::warn!("turtle {} got an invalid action {}: {}", id, text, e);
+                continue;
+            }
+        };
+        tracing::debug!("turtle {} running {:?}", id, action);
+
+        if !options.delay.is_zero() {
+            tokio::time::sleep(options.delay).await;
+        }
+
+        let Some(resp) = world.lock().await.apply(id, action.id, &action.action) else {
+            break;
+        };
+        let resp = serde_json::to_string(&resp).expect("response serializes");
+        tx.send(Message::Text(resp)).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,699 @@
+use std::collections::HashMap;
+
+use models::{
+    ActionResult, ActionType, Block, BlockState, Chest, ChestAction, Direction, InfoResp,
+    ItemDetail, MineDirection, MoveDirection, Outcome, Position, Slot, Slot2, TurnDirection,
+    TurtleKind, Type,
+};
+
+pub const AIR: &str = "minecraft:air";
+const CHEST: &str = "minecraft:chest";
+const SLOTS: usize = 16;
+const CHEST_SLOTS: usize = 27;
+const STACK_SIZE: i64 = 64;
+/// Layers below this are deepslate, like in vanilla.
+const DEEPSLATE_Y: i64 = 0;
+const BEDROCK_Y: i64 = -64;
+
+const UNBREAKABLE: &[&str] = &["minecraft:bedrock", "minecraft:barrier"];
+
+/// Fuel points per item, as in ComputerCraft.
+fn fuel_value(item: &str) -> Option<i64> {
+    match item {
+        "minecraft:coal" | "minecraft:charcoal" => Some(80),
+        "minecraft:coal_block" => Some(800),
+        "minecraft:lava_bucket" => Some(1000),
+        "minecraft:blaze_rod" => Some(120),
+        name if name.ends_with("_log") || name.ends_with("_planks") => Some(15),
+        _ => None,
+    }
+}
+
+fn failed(error: &str) -> Outcome {
+    Outcome {
+        ok: false,
+        error: Some(error.to_string()),
+    }
+}
+
+/// Item a mined block drops.
+fn drop_of(block: &str) -> Option<String> {
+    let item = match block {
+        AIR => return None,
+        "minecraft:stone" => "minecraft:cobblestone",
+        "minecraft:deepslate" => "minecraft:cobbled_deepslate",
+        "minecraft:grass_block" => "minecraft:dirt",
+        "minecraft:coal_ore" | "minecraft:deepslate_coal_ore" => "minecraft:coal",
+        "minecraft:iron_ore" | "minecraft:deepslate_iron_ore" => "minecraft:raw_iron",
+        "minecraft:gold_ore" | "minecraft:deepslate_gold_ore" => "minecraft:raw_gold",
+        "minecraft:diamond_ore" | "minecraft:deepslate_diamond_ore" => "minecraft:diamond",
+        name => name,
+    };
+
+    Some(item.to_string())
+}
+
+/// Cheap deterministic hash of a position, used to scatter ore.
+fn noise(pos: Position, seed: u64) -> u64 {
+    let mut h = seed ^ 0x9e37_79b9_7f4a_7c15;
+    for v in [pos.x, pos.y, pos.z] {
+        h ^= v as u64;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+    }
+    h
+}
+
+/// An in-memory voxel world. Cells that were never changed are generated
+/// from the seed: bedrock at the bottom, stone and deepslate with scattered
+/// ore up to `surface`, a layer of dirt under grass, and air above.
+#[derive(Debug)]
+pub struct World {
+    pub seed: u64,
+    pub surface: i64,
+    changed: HashMap<Position, String>,
+    chests: HashMap<Position, Vec<Option<Slot2>>>,
+    turtles: HashMap<usize, SimTurtle>,
+}
+
+impl World {
+    pub fn new(seed: u64, surface: i64) -> Self {
+        Self {
+            seed,
+            surface,
+            changed: HashMap::new(),
+            chests: HashMap::new(),
+            turtles: HashMap::new(),
+        }
+    }
+
+    fn generated(&self, pos: Position) -> &'static str {
+        if pos.y > self.surface {
+            return AIR;
+        }
+        if pos.y == self.surface {
+            return "minecraft:grass_block";
+        }
+        if pos.y > self.surface - 4 {
+            return "minecraft:dirt";
+        }
+        if pos.y <= BEDROCK_Y {
+            return "minecraft:bedrock";
+        }
+
+        let deep = pos.y < DEEPSLATE_Y;
+        let roll = noise(pos, self.seed) % 1000;
+        let ore = match roll {
+            0..=9 => Some("coal"),
+            10..=15 => Some("iron"),
+            16..=17 if pos.y < 32 => Some("gold"),
+            18 if pos.y < 16 => Some("diamond"),
+            _ => None,
+        };
+        match (ore, deep) {
+            (Some("coal"), false) => "minecraft:coal_ore",
+            (Some("coal"), true) => "minecraft:deepslate_coal_ore",
+            (Some("iron"), false) => "minecraft:iron_ore",
+            (Some("iron"), true) => "minecraft:deepslate_iron_ore",
+            (Some("gold"), false) => "minecraft:gold_ore",
+            (Some("gold"), true) => "minecraft:deepslate_gold_ore",
+            (Some(_), false) => "minecraft:diamond_ore",
+            (Some(_), true) => "minecraft:deepslate_diamond_ore",
+            (None, false) => "minecraft:stone",
+            (None, true) => "minecraft:deepslate",
+        }
+    }
+
+    /// The block at `pos`, including turtles standing there.
+    pub fn block(&self, pos: Position) -> String {
+        if let Some(turtle) = self.turtles.values().find(|t| t.pos == pos) {
+            return match turtle.kind {
+                TurtleKind::Normal => "computercraft:turtle_normal".to_string(),
+                TurtleKind::Advanced => "computercraft:turtle_advanced".to_string(),
+            };
+        }
+
+        self.changed
+            .get(&pos)
+            .cloned()
+            .unwrap_or_else(|| self.generated(pos).to_string())
+    }
+
+    pub fn set(&mut self, pos: Position, block: &str) {
+        self.changed.insert(pos, block.to_string());
+        if block != CHEST {
+            self.chests.remove(&pos);
+        }
+    }
+
+    /// Places a chest at `pos` holding `items`.
+    pub fn place_chest(&mut self, pos: Position, items: Vec<Slot2>) {
+        self.set(pos, CHEST);
+        let mut slots: Vec<Option<Slot2>> = items.into_iter().map(Some).collect();
+        slots.resize(CHEST_SLOTS, None);
+        self.chests.insert(pos, slots);
+    }
+
+    pub fn add_turtle(&mut self, turtle: SimTurtle) {
+        self.turtles.insert(turtle.id, turtle);
+    }
+
+    pub fn turtle(&self, id: usize) -> Option<&SimTurtle> {
+        self.turtles.get(&id)
+    }
+
+    /// Runs `action` for turtle `id` and returns the reply a real turtle
+    /// would send. Actions that fail in game, such as moving into stone,
+    /// leave the turtle unchanged.
+    pub fn apply(&mut self, id: usize, request: u64, action: &ActionType) -> Option<InfoResp> {
+        let mut turtle = self.turtles.remove(&id)?;
+        let result = match action {
+            ActionType::Move(dir) => {
+                self.move_turtle(&mut turtle, *dir);
+                None
+            }
+            ActionType::Turn(dir) => {
+                turtle.facing = match dir {
+                    TurnDirection::Left => turtle.facing.left(),
+                    TurnDirection::Right => turtle.facing.right(),
+                };
+                None
+            }
+            ActionType::Mine(dir) => {
+                self.mine(&mut turtle, *dir);
+                None
+            }
+            ActionType::Refuel { count } => {
+                turtle.refuel(*count);
+                None
+            }
+            ActionType::Chest(ChestAction::Deposit) => {
+                self.drop_items(&mut turtle, MineDirection::Down, None);
+                None
+            }
+            ActionType::Chest(ChestAction::Withdraw) => {
+                self.suck(&mut turtle, MineDirection::Down, None);
+                None
+            }
+            ActionType::Select(slot) => {
+                turtle.select(*slot);
+                None
+            }
+            ActionType::Slot { name, args } if name == "Select" => {
+                if let Some(slot) = args.first() {
+                    turtle.select(*slot);
+                }
+                None
+            }
+            ActionType::Slot { name, .. } => {
+                tracing::warn!("turtle {} ignoring unknown slot action {}", id, name);
+                None
+            }
+            ActionType::Interact | ActionType::Info => None,
+            ActionType::Place(dir) => Some(ActionResult::Outcome(self.place(&mut turtle, *dir))),
+            ActionType::Drop { direction, count } => Some(ActionResult::Outcome(self.drop_items(
+                &mut turtle,
+                *direction,
+                *count,
+            ))),
+            ActionType::Suck { direction, count } => Some(ActionResult::Outcome(self.suck(
+                &mut turtle,
+                *direction,
+                *count,
+            ))),
+            ActionType::Inspect(dir) => {
+                let name = self.block(turtle.pos.looking(turtle.facing, *dir));
+                Some(ActionResult::Block((name != AIR).then(|| BlockState {
+                    name,
+                    state: HashMap::new(),
+                    tags: vec![],
+                })))
+            }
+            ActionType::Compare(dir) => {
+                let name = self.block(turtle.pos.looking(turtle.facing, *dir));
+                let same = match &turtle.slots[turtle.selected] {
+                    Some(stack) => stack.name == name,
+                    None => name == AIR,
+                };
+                Some(ActionResult::Outcome(Outcome {
+                    ok: same,
+                    error: None,
+                }))
+            }
+            ActionType::Attack(_) => Some(ActionResult::Outcome(failed("Nothing to attack here"))),
+            ActionType::TransferTo { slot, count } => {
+                Some(ActionResult::Outcome(turtle.transfer_to(*slot, *count)))
+            }
+            ActionType::GetItemDetail { slot, .. } => {
+                let slot = slot.map_or(turtle.selected, |s| (s - 1).max(0) as usize);
+                let item = turtle.slots.get(slot).cloned().flatten();
+                Some(ActionResult::Item(item.map(|s| ItemDetail {
+                    name: s.name,
+                    count: s.count,
+                    nbt: s.nbt,
+                    display_name: None,
+                    damage: None,
+                    max_damage: None,
+                    tags: vec![],
+                })))
+            }
+            ActionType::Equip(_) => Some(ActionResult::Outcome(failed("Not a valid upgrade"))),
+            ActionType::Craft(_) => Some(ActionResult::Outcome(failed("No matching recipes"))),
+        };
+        self.turtles.insert(id, turtle);
+
+        let mut resp = self.info(id, request);
+        resp.result = result;
+        Some(resp)
+    }
+
+    fn move_turtle(&self, turtle: &mut SimTurtle, dir: MoveDirection) {
+        let target = match dir {
+            MoveDirection::Forward => turtle.pos.step(turtle.facing),
+            MoveDirection::Backward => turtle.pos.step(turtle.facing.left().left()),
+            MoveDirection::Up => turtle.pos.offset(0, 1, 0),
+            MoveDirection::Down => turtle.pos.offset(0, -1, 0),
+        };
+
+        if turtle.fuel <= 0 || self.block(target) != AIR {
+            return;
+        }
+        turtle.fuel -= 1;
+        turtle.pos = target;
+    }
+
+    fn mine(&mut self, turtle: &mut SimTurtle, dir: MineDirection) {
+        let target = turtle.pos.looking(turtle.facing, dir);
+        let block = self.block(target);
+        if block.starts_with("computercraft:turtle") || UNBREAKABLE.contains(&block.as_str()) {
+            return;
+        }
+
+        let Some(item) = drop_of(&block) else {
+            return;
+        };
+        if block == CHEST {
+            // the chest keeps its contents in game too, but they spill out;
+            // here they are simply lost
+            self.chests.remove(&target);
+        }
+        self.set(target, AIR);
+        if !turtle.insert(&item, 1) {
+            tracing::debug!("turtle {} inventory full, {} dropped", turtle.id, item);
+        }
+    }
+
+    /// Places the selected item as a block of the same name.
+    fn place(&mut self, turtle: &mut SimTurtle, dir: MineDirection) -> Outcome {
+        let target = turtle.pos.looking(turtle.facing, dir);
+        let Some(stack) = &mut turtle.slots[turtle.selected] else {
+            return failed("No items to place");
+        };
+        if self.block(target) != AIR {
+            return failed("Cannot place block here");
+        }
+
+        self.set(target, &stack.name.clone());
+        stack.count -= 1;
+        if stack.count == 0 {
+            turtle.slots[turtle.selected] = None;
+        }
+        Outcome {
+            ok: true,
+            error: None,
+        }
+    }
+
+    /// Drops up to `count` of the selected stack into the chest in `dir`,
+    /// or onto the ground, where it is lost, if there is no chest.
+    fn drop_items(
+        &mut self,
+        turtle: &mut SimTurtle,
+        dir: MineDirection,
+        count: Option<i64>,
+    ) -> Outcome {
+        let target = turtle.pos.looking(turtle.facing, dir);
+        let Some(stack) = turtle.slots[turtle.selected].take() else {
+            return failed("No items to drop");
+        };
+
+        let amount = count.unwrap_or(stack.count).min(stack.count);
+        let mut left = amount;
+        if let Some(chest) = self.chests.get_mut(&target) {
+            for slot in chest.iter_mut() {
+                match slot {
+                    Some(s) if s.name == stack.name && s.count < STACK_SIZE => {
+                        let moved = left.min(STACK_SIZE - s.count);
+                        s.count += moved;
+                        left -= moved;
+                    }
+                    None => {
+                        *slot = Some(Slot2 {
+                            count: left,
+                            ..stack.clone()
+                        });
+                        left = 0;
+                    }
+                    _ => {}
+                }
+                if left == 0 {
+                    break;
+                }
+            }
+        } else {
+            left = 0;
+        }
+
+        let kept = stack.count - amount + left;
+        if kept > 0 {
+            turtle.slots[turtle.selected] = Some(Slot2 {
+                count: kept,
+                ..stack
+            });
+        }
+        if left == amount {
+            return failed("No space for items");
+        }
+        Outcome {
+            ok: true,
+            error: None,
+        }
+    }
+
+    /// Takes up to `count` items of the first stack in the chest in `dir`.
+    fn suck(&mut self, turtle: &mut SimTurtle, dir: MineDirection, count: Option<i64>) -> Outcome {
+        let target = turtle.pos.looking(turtle.facing, dir);
+        let Some(slot) = self
+            .chests
+            .get_mut(&target)
+            .and_then(|chest| chest.iter_mut().find(|s| s.is_some()))
+        else {
+            return failed("No items to take");
+        };
+
+        let stack = slot.take().expect("slot was just found");
+        let wanted = count.unwrap_or(STACK_SIZE).min(stack.count);
+        let mut taken = 0;
+        while taken < wanted && turtle.insert(&stack.name, 1) {
+            taken += 1;
+        }
+        if taken < stack.count {
+            *slot = Some(Slot2 {
+                count: stack.count - taken,
+                ..stack
+            });
+        }
+
+        if taken == 0 {
+            return failed("No space for items");
+        }
+        Outcome {
+            ok: true,
+            error: None,
+        }
+    }
+
+    pub fn info(&self, id: usize, request: u64) -> InfoResp {
+        let turtle = &self.turtles[&id];
+        let blocks = [
+            MineDirection::Forward,
+            MineDirection::Up,
+            MineDirection::Down,
+        ]
+        .map(|direction| {
+            let name = self.block(turtle.pos.looking(turtle.facing, direction));
+            let exists = name != AIR;
+            Block {
+                direction,
+                exists,
+                block: exists.then_some(name),
+                state: HashMap::new(),
+            }
+        })
+        .to_vec();
+
+        let chest = self
+            .chests
+            .get(&turtle.pos.offset(0, -1, 0))
+            .map(|slots| Chest {
+                slots: slots.iter().flatten().cloned().collect(),
+                size: CHEST_SLOTS as i64,
+            });
+
+        InfoResp {
+            id: request,
+            chest,
+            fuel: turtle.fuel,
+            slots: turtle.slot_info(),
+            blocks,
+            pos: turtle.pos,
+            result: None,
+        }
+    }
+}
+
+/// A simulated turtle. Slots are numbered from 1 on the wire, like in Lua.
+#[derive(Debug, Clone)]
+pub struct SimTurtle {
+    pub id: usize,
+    pub kind: TurtleKind,
+    pub pos: Position,
+    pub facing: Direction,
+    pub fuel: i64,
+    pub selected: usize,
+    pub slots: Vec<Option<Slot2>>,
+}
+
+impl SimTurtle {
+    pub fn new(id: usize, kind: TurtleKind, pos: Position, facing: Direction, fuel: i64) -> Self {
+        Self {
+            id,
+            kind,
+            pos,
+            facing,
+            fuel,
+            selected: 0,
+            slots: vec![None; SLOTS],
+        }
+    }
+
+    /// Moves up to `count` items from the selected slot into `slot`.
+    fn transfer_to(&mut self, slot: i64, count: Option<i64>) -> Outcome {
+        let to = (slot - 1) as usize;
+        if !(1..=SLOTS as i64).contains(&slot) {
+            return failed("Slot out of range");
+        }
+        let Some(stack) = self.slots[self.selected].clone() else {
+            return failed("No items to transfer");
+        };
+        if to == self.selected {
+            return Outcome {
+                ok: true,
+                error: None,
+            };
+        }
+
+        let room = match &self.slots[to] {
+            None => STACK_SIZE,
+            Some(s) if s.name == stack.name => STACK_SIZE - s.count,
+            Some(_) => 0,
+        };
+        let moved = count.unwrap_or(stack.count).min(stack.count).min(room);
+        if moved == 0 {
+            return failed("No space for items");
+        }
+
+        match &mut self.slots[to] {
+            Some(s) => s.count += moved,
+            empty => {
+                *empty = Some(Slot2 {
+                    count: moved,
+                    ..stack.clone()
+                })
+            }
+        }
+        if stack.count == moved {
+            self.slots[self.selected] = None;
+        } else if let Some(s) = &mut self.slots[self.selected] {
+            s.count -= moved;
+        }
+
+        Outcome {
+            ok: true,
+            error: None,
+        }
+    }
+
+    fn select(&mut self, slot: i64) {
+        if (1..=SLOTS as i64).contains(&slot) {
+            self.selected = slot as usize - 1;
+        }
+    }
+
+    fn fuel_limit(&self) -> i64 {
+        match self.kind {
+            TurtleKind::Normal => 20_000,
+            TurtleKind::Advanced => 100_000,
+        }
+    }
+
+    /// Adds `count` of `item`, filling matching stacks first. Returns false
+    /// if it did not all fit.
+    pub fn insert(&mut self, item: &str, count: i64) -> bool {
+        let mut left = count;
+        for slot in self.slots.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            match slot {
+                Some(s) if s.name == item && s.count < STACK_SIZE => {
+                    let moved = left.min(STACK_SIZE - s.count);
+                    s.count += moved;
+                    left -= moved;
+                }
+                _ => {}
+            }
+        }
+        for slot in self.slots.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            if slot.is_none() {
+                let moved = left.min(STACK_SIZE);
+                *slot = Some(Slot2 {
+                    name: item.to_string(),
+                    count: moved,
+                    nbt: None,
+                });
+                left -= moved;
+            }
+        }
+
+        left == 0
+    }
+
+    /// Burns at most `count` items of the selected stack, like
+    /// `turtle.refuel(count)`, or the whole stack if `None`. Burning stops
+    /// at the fuel limit and items that are not fuel are left alone.
+    fn refuel(&mut self, count: Option<i64>) {
+        let limit = self.fuel_limit();
+        let Some(stack) = &mut self.slots[self.selected] else {
+            return;
+        };
+        let Some(value) = fuel_value(&stack.name) else {
+            return;
+        };
+
+        let mut left = count.unwrap_or(i64::MAX);
+        while stack.count > 0 && left > 0 && self.fuel < limit {
+            self.fuel = (self.fuel + value).min(limit);
+            stack.count -= 1;
+            left -= 1;
+        }
+        if stack.name == "minecraft:lava_bucket" && stack.count == 0 {
+            stack.name = "minecraft:bucket".to_string();
+            stack.count = 1;
+        }
+        if stack.count == 0 {
+            self.slots[self.selected] = None;
+        }
+    }
+
+    fn slot_info(&self) -> Vec<Slot> {
+        self.slots
+            .iter()
+            .enumerate()
+            .map(|(i, slot)| Slot {
+                id: i as i64 + 1,
+                type_field: slot.as_ref().map(|s| Type {
+                    name: s.name.clone(),
+                    count: s.count,
+                }),
+                space: STACK_SIZE - slot.as_ref().map_or(0, |s| s.count),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: Position = Position { x: 0, y: 10, z: 0 };
+
+    fn world_with_turtle(fuel: i64) -> World {
+        let mut world = World::new(1, 64);
+        world.set(START, AIR);
+        world.add_turtle(SimTurtle::new(
+            1,
+            TurtleKind::Normal,
+            START,
+            Direction::North,
+            fuel,
+        ));
+        world
+    }
+
+    #[test]
+    fn moving_into_stone_fails_until_mined() {
+        let mut world = world_with_turtle(10);
+        world.set(START.step(Direction::North), "minecraft:stone");
+
+        let resp = world
+            .apply(1, 1, &ActionType::Move(MoveDirection::Forward))
+            .unwrap();
+        assert_eq!(resp.pos, START);
+        assert_eq!(resp.fuel, 10);
+
+        world.apply(1, 2, &ActionType::Mine(MineDirection::Forward));
+        let resp = world
+            .apply(1, 3, &ActionType::Move(MoveDirection::Forward))
+            .unwrap();
+        assert_eq!(resp.pos, START.step(Direction::North));
+        assert_eq!(resp.fuel, 9);
+        assert_eq!(resp.id, 3);
+
+        let cobble = resp.slots[0].type_field.as_ref().unwrap();
+        assert_eq!(cobble.name, "minecraft:cobblestone");
+        assert_eq!(resp.slots[0].space, 63);
+    }
+
+    #[test]
+    fn refuel_burns_the_selected_stack() {
+        let mut world = world_with_turtle(0);
+        world
+            .turtles
+            .get_mut(&1)
+            .unwrap()
+            .insert("minecraft:coal", 3);
+
+        let resp = world
+            .apply(1, 1, &ActionType::Refuel { count: None })
+            .unwrap();
+        assert_eq!(resp.fuel, 240);
+        assert!(resp.slots[0].type_field.is_none());
+    }
+
+    #[test]
+    fn chest_round_trip() {
+        let mut world = world_with_turtle(0);
+        world.place_chest(START.offset(0, -1, 0), vec![]);
+        world
+            .turtles
+            .get_mut(&1)
+            .unwrap()
+            .insert("minecraft:dirt", 5);
+
+        let resp = world
+            .apply(1, 1, &ActionType::Chest(ChestAction::Deposit))
+            .unwrap();
+        assert!(resp.slots[0].type_field.is_none());
+        assert_eq!(resp.chest.unwrap().slots[0].count, 5);
+
+        let resp = world
+            .apply(1, 2, &ActionType::Chest(ChestAction::Withdraw))
+            .unwrap();
+        assert_eq!(resp.slots[0].type_field.as_ref().unwrap().count, 5);
+        assert!(resp.chest.unwrap().slots.is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use models::{Chest, ChestContents, ItemLocation, Position};
+
+use crate::models::unix_time;
+
+/// Every chest a turtle has read, keyed by the chest's position. Turtles
+/// read the chest below them with each reply, so the index stays as fresh
+/// as the turtles' trips past their chests.
+#[derive(Debug, Default)]
+pub struct ChestIndex {
+    chests: HashMap<Position, ChestContents>,
+    dirty: HashSet<Position>,
+}
+
+impl ChestIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records what a turtle standing at `turtle_pos` saw below it. `None`
+    /// means there is no chest there (any more).
+    pub fn observe(&mut self, turtle_pos: Position, chest: Option<&Chest>) {
+        let pos = turtle_pos.offset(0, -1, 0);
+        match chest {
+            Some(chest) => {
+                let contents = ChestContents {
+                    pos,
+                    chest: chest.clone(),
+                    seen_at: unix_time(),
+                };
+                self.chests.insert(pos, contents);
+            }
+            None => {
+                if self.chests.remove(&pos).is_none() {
+                    return;
+                }
+            }
+        }
+        self.dirty.insert(pos);
+    }
+
+    /// Restores a chest loaded from storage without marking it dirty.
+    pub fn load(&mut self, contents: ChestContents) {
+        self.chests.insert(contents.pos, contents);
+    }
+
+    pub fn get(&self, pos: &Position) -> Option<&ChestContents> {
+        self.chests.get(pos)
+    }
+
+    pub fn list(&self) -> Vec<ChestContents> {
+        let mut chests: Vec<_> = self.chests.values().cloned().collect();
+        chests.sort_by_key(|c| c.pos);
+        chests
+    }
+
+    /// Chests holding items whose name contains `item`, most items first.
+    /// `"iron"` finds both raw iron and ingots.
+    pub fn find(&self, item: &str) -> Vec<ItemLocation> {
+        let mut found: BTreeMap<(Position, &str), ItemLocation> = BTreeMap::new();
+        for contents in self.chests.values() {
+            for slot in contents
+                .chest
+                .slots
+                .iter()
+                .filter(|s| s.name.contains(item))
+            {
+                found
+                    .entry((contents.pos, slot.name.as_str()))
+                    .or_insert_with(|| ItemLocation {
+                        pos: contents.pos,
+                        name: slot.name.clone(),
+                        count: 0,
+                        seen_at: contents.seen_at,
+                    })
+                    .count += slot.count;
+            }
+        }
+
+        let mut found: Vec<_> = found.into_values().collect();
+        found.sort_by_key(|l| Reverse(l.count));
+        found
+    }
+
+    /// Item counts summed over every known chest.
+    pub fn totals(&self) -> BTreeMap<String, i64> {
+        let mut totals = BTreeMap::new();
+        for slot in self.chests.values().flat_map(|c| &c.chest.slots) {
+            *totals.entry(slot.name.clone()).or_insert(0) += slot.count;
+        }
+        totals
+    }
+
+    /// Chests changed since the last call, for writing to storage. Removed
+    /// chests come back as `None`.
+    pub fn take_dirty(&mut self) -> Vec<(Position, Option<ChestContents>)> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty
+            .into_iter()
+            .map(|p| (p, self.chests.get(&p).cloned()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::Slot2;
+
+    use super::*;
+
+    fn chest(items: &[(&str, i64)]) -> Chest {
+        Chest {
+            slots: items
+                .iter()
+                .map(|(name, count)| Slot2 {
+                    name: name.to_string(),
+                    count: *count,
+                    nbt: None,
+                })
+                .collect(),
+            size: 27,
+        }
+    }
+
+    #[test]
+    fn finds_items_by_partial_name_and_sums_totals() {
+        let mut index = ChestIndex::new();
+        let a = Position { x: 0, y: 65, z: 0 };
+        let b = Position { x: 4, y: 65, z: 0 };
+        index.observe(
+            a,
+            Some(&chest(&[
+                ("minecraft:raw_iron", 64),
+                ("minecraft:raw_iron", 10),
+            ])),
+        );
+        index.observe(
+            b,
+            Some(&chest(&[
+                ("minecraft:iron_ingot", 80),
+                ("minecraft:coal", 5),
+            ])),
+        );
+
+        let found = index.find("iron");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name, "minecraft:iron_ingot");
+        assert_eq!(found[0].pos, b.offset(0, -1, 0));
+        assert_eq!(found[1].count, 74);
+
+        let totals = index.totals();
+        assert_eq!(totals["minecraft:raw_iron"], 74);
+        assert_eq!(totals["minecraft:coal"], 5);
+    }
+
+    #[test]
+    fn a_missing_chest_is_forgotten() {
+        let mut index = ChestIndex::new();
+        let at = Position { x: 0, y: 65, z: 0 };
+        index.observe(at, Some(&chest(&[("minecraft:coal", 5)])));
+        index.take_dirty();
+
+        index.observe(at, None);
+
+        assert!(index.get(&at.offset(0, -1, 0)).is_none());
+        assert_eq!(index.take_dirty(), vec![(at.offset(0, -1, 0), None)]);
+    }
+}
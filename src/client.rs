@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use models::PROTOCOL_VERSION;
+
+use crate::models::TurtleManager;
+
+const CLIENT: &str = include_str!("../lua/client.lua");
+const INSTALL: &str = include_str!("../lua/install.lua");
+
+/// Base http URL of the server: `public_url` from the config, or else the
+/// host the request was sent to.
+fn http_url(manager: &TurtleManager, headers: &HeaderMap) -> String {
+    if let Some(url) = &manager.config.public_url {
+        return url.trim_end_matches('/').to_string();
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost:1337");
+    format!("http://{}", host)
+}
+
+fn lua(script: String) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/x-lua; charset=utf-8")],
+        script,
+    )
+}
+
+/// The turtle client, pointed at this server's websocket.
+pub async fn client_script(
+    State(manager): State<Arc<TurtleManager>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let url = http_url(&manager, &headers);
+    let ws_url = match url.strip_prefix("https://") {
+        Some(rest) => format!("wss://{}/ws", rest),
+        None => format!("ws://{}/ws", url.trim_start_matches("http://")),
+    };
+
+    lua(CLIENT
+        .replace("{{SERVER_URL}}", &ws_url)
+        .replace("{{PROTOCOL_VERSION}}", &PROTOCOL_VERSION.to_string()))
+}
+
+/// Bootstrap that installs the client, for `wget run <server>/install.lua`.
+pub async fn install_script(
+    State(manager): State<Arc<TurtleManager>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    lua(INSTALL.replace("{{HTTP_URL}}", &http_url(&manager, &headers)))
+}
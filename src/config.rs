@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use models::Position;
+use serde::{Deserialize, Deserializer};
+
+const CONFIG_ENV: &str = "TURTLES_CONFIG";
+const DEFAULT_PATH: &str = "config.json";
+
+/// Server settings, read from the JSON file named by `TURTLES_CONFIG`
+/// (`config.json` by default). Missing keys fall back to the defaults below.
+///
+/// Chest positions are the chest block itself; turtles stand on top of it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Fuel level below which a turtle drops what it is doing to refuel.
+    pub refuel_threshold: i64,
+    /// Fuel level a refuelling turtle tops up to before resuming.
+    pub refuel_target: i64,
+    /// Fuel points per item, only items listed here are burnt.
+    pub fuel_values: HashMap<String, i64>,
+    /// Chest to take fuel from when the inventory has none.
+    pub fuel_chest: Option<Position>,
+    /// Chest a full turtle empties its inventory into.
+    pub deposit_chest: Option<Position>,
+    /// Named slots, such as `"fuel": 16`, that depositing and junk dropping
+    /// never touch. The plain list of slot numbers older configs use is
+    /// still read, each slot named after its number.
+    #[serde(deserialize_with = "reserved_slots")]
+    pub reserved_slots: HashMap<String, i64>,
+    /// Items a full turtle throws away to make room.
+    pub junk_items: Vec<String>,
+    /// Blocks between the branches of a strip mine.
+    pub branch_spacing: i64,
+    /// Length of each strip mine branch.
+    pub branch_length: i64,
+    /// Block ids that count as ore. A strip mine follows the veins of those
+    /// belonging to the material it is after.
+    pub ore_blocks: Vec<String>,
+    /// Seconds a turtle may stay offline before its share of a split job is
+    /// handed to another turtle.
+    pub reassign_after: u64,
+    /// Sapling grid turtles with the `TreeFarm` goal look after.
+    pub tree_farm: Option<TreeFarm>,
+    /// Crop plot turtles with the `Farm` goal look after.
+    pub crop_farm: Option<CropFarm>,
+    /// Address turtles reach the server at, such as `http://example.com:1337`.
+    /// Defaults to the host the client script is downloaded from.
+    pub public_url: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refuel_threshold: 200,
+            refuel_target: 2000,
+            fuel_values: HashMap::from([
+                ("minecraft:coal".to_string(), 80),
+                ("minecraft:charcoal".to_string(), 80),
+                ("minecraft:coal_block".to_string(), 800),
+                ("minecraft:lava_bucket".to_string(), 1000),
+                ("minecraft:blaze_rod".to_string(), 120),
+                ("minecraft:oak_log".to_string(), 15),
+                ("minecraft:spruce_log".to_string(), 15),
+                ("minecraft:birch_log".to_string(), 15),
+                ("minecraft:jungle_log".to_string(), 15),
+                ("minecraft:acacia_log".to_string(), 15),
+                ("minecraft:dark_oak_log".to_string(), 15),
+                ("minecraft:mangrove_log".to_string(), 15),
+                ("minecraft:cherry_log".to_string(), 15),
+            ]),
+            fuel_chest: None,
+            deposit_chest: None,
+            reserved_slots: HashMap::new(),
+            junk_items: [
+                "minecraft:cobblestone",
+                "minecraft:cobbled_deepslate",
+                "minecraft:dirt",
+                "minecraft:gravel",
+                "minecraft:netherrack",
+            ]
+            .map(String::from)
+            .to_vec(),
+            branch_spacing: 3,
+            branch_length: 16,
+            ore_blocks: [
+                "minecraft:coal_ore",
+                "minecraft:deepslate_coal_ore",
+                "minecraft:iron_ore",
+                "minecraft:deepslate_iron_ore",
+                "minecraft:copper_ore",
+                "minecraft:deepslate_copper_ore",
+                "minecraft:gold_ore",
+                "minecraft:deepslate_gold_ore",
+                "minecraft:redstone_ore",
+                "minecraft:deepslate_redstone_ore",
+                "minecraft:lapis_ore",
+                "minecraft:deepslate_lapis_ore",
+                "minecraft:diamond_ore",
+                "minecraft:deepslate_diamond_ore",
+                "minecraft:emerald_ore",
+                "minecraft:deepslate_emerald_ore",
+            ]
+            .map(String::from)
+            .to_vec(),
+            reassign_after: 60,
+            tree_farm: None,
+            crop_farm: None,
+            public_url: None,
+        }
+    }
+}
+
+/// A grid of sapling spots on flat ground, `columns` along x and `rows`
+/// along z from `origin`. Turtles work the grid from the cell south of each
+/// spot, so spots need at least one free block between them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TreeFarm {
+    /// The spot in the north-west corner.
+    pub origin: Position,
+    pub rows: i64,
+    pub columns: i64,
+    /// Distance between neighbouring spots.
+    pub spacing: i64,
+    pub sapling: String,
+    /// Chest the logs go to.
+    pub chest: Position,
+    /// Seconds to rest between patrols.
+    #[serde(default = "default_patrol_interval")]
+    pub patrol_interval: u64,
+}
+
+/// A flat plot of farmland between the corners `a` and `b`, which sit at
+/// the height of the crops. Turtles fly over it one block up.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CropFarm {
+    pub a: Position,
+    pub b: Position,
+    /// Chest the harvest goes to.
+    pub chest: Position,
+    /// What to plant in cells where nothing grows.
+    #[serde(default = "default_seed")]
+    pub seed: String,
+    /// Seconds to rest between patrols.
+    #[serde(default = "default_patrol_interval")]
+    pub patrol_interval: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReservedSlots {
+    Named(HashMap<String, i64>),
+    Listed(Vec<i64>),
+}
+
+fn reserved_slots<'de, D: Deserializer<'de>>(d: D) -> Result<HashMap<String, i64>, D::Error> {
+    Ok(match ReservedSlots::deserialize(d)? {
+        ReservedSlots::Named(slots) => slots,
+        ReservedSlots::Listed(slots) => slots.into_iter().map(|s| (s.to_string(), s)).collect(),
+    })
+}
+
+fn default_seed() -> String {
+    "minecraft:wheat_seeds".to_string()
+}
+
+fn default_patrol_interval() -> u64 {
+    60
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let path = std::env::var(CONFIG_ENV).unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .unwrap_or_else(|e| panic!("invalid config file {}: {}", path, e)),
+            Err(_) => {
+                tracing::warn!("no config file at {}, using defaults", path);
+                Self::default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_slots_are_read_as_names_or_a_list() {
+        let cases = [
+            (r#"{"reserved_slots": {"fuel": 16}}"#, [("fuel", 16)]),
+            (r#"{"reserved_slots": [16]}"#, [("16", 16)]),
+        ];
+
+        for (text, expected) in cases {
+            let config: Config = serde_json::from_str(text).unwrap();
+            let expected = expected.map(|(name, slot)| (name.to_string(), slot));
+            assert_eq!(config.reserved_slots, HashMap::from(expected), "{}", text);
+        }
+    }
+}
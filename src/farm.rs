@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use models::{Block, Position, QueuedAction};
+
+use crate::config::CropFarm;
+use crate::world::bounds;
+
+/// Seeds a turtle keeps of each crop for replanting when it stores its
+/// harvest.
+const SEEDS_KEPT: i64 = 64;
+
+/// A crop the farm knows how to harvest and replant.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub block: &'static str,
+    /// Item that plants the crop again.
+    pub seed: &'static str,
+    /// `age` block state of a crop ready to harvest.
+    pub ripe_age: i64,
+}
+
+pub const CROPS: [Crop; 3] = [
+    Crop {
+        block: "minecraft:wheat",
+        seed: "minecraft:wheat_seeds",
+        ripe_age: 7,
+    },
+    Crop {
+        block: "minecraft:carrots",
+        seed: "minecraft:carrot",
+        ripe_age: 7,
+    },
+    Crop {
+        block: "minecraft:potatoes",
+        seed: "minecraft:potato",
+        ripe_age: 7,
+    },
+];
+
+pub fn crop(block: &str) -> Option<&'static Crop> {
+    CROPS.iter().find(|c| c.block == block)
+}
+
+pub fn is_ripe(crop: &Crop, block: &Block) -> bool {
+    block
+        .state
+        .get("age")
+        .and_then(|age| age.as_i64())
+        .is_some_and(|age| age >= crop.ripe_age)
+}
+
+/// Every crop cell of the plot.
+pub fn cells(farm: &CropFarm) -> Vec<Position> {
+    let (min, max) = bounds(farm.a, farm.b);
+    (min.z..=max.z)
+        .flat_map(|z| (min.x..=max.x).map(move |x| Position { x, y: min.y, z }))
+        .collect()
+}
+
+/// One round of the plot: tend every cell, row by row with each row walked
+/// the opposite way to the one before, then store the surplus and rest.
+pub fn plan_patrol(farm: &CropFarm) -> VecDeque<QueuedAction> {
+    let (min, max) = bounds(farm.a, farm.b);
+    let mut actions = VecDeque::new();
+    for (row, z) in (min.z..=max.z).enumerate() {
+        let mut xs: Vec<i64> = (min.x..=max.x).collect();
+        if row % 2 == 1 {
+            xs.reverse();
+        }
+        for x in xs {
+            actions.push_back(QueuedAction::TendCrop {
+                pos: Position { x, y: min.y, z },
+                seed: farm.seed.clone(),
+            });
+        }
+    }
+
+    actions.push_back(QueuedAction::Store {
+        chest: farm.chest,
+        keep: CROPS
+            .iter()
+            .map(|c| (c.seed.to_string(), SEEDS_KEPT))
+            .collect(),
+    });
+    actions.push_back(QueuedAction::Wait(farm.patrol_interval));
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use models::MineDirection;
+
+    use super::*;
+
+    fn wheat(age: i64) -> Block {
+        Block {
+            direction: MineDirection::Down,
+            exists: true,
+            block: Some("minecraft:wheat".to_string()),
+            state: HashMap::from([("age".to_string(), serde_json::json!(age))]),
+        }
+    }
+
+    #[test]
+    fn only_fully_grown_crops_are_ripe() {
+        let crop = crop("minecraft:wheat").unwrap();
+
+        assert!(is_ripe(crop, &wheat(7)));
+        assert!(!is_ripe(crop, &wheat(6)));
+        assert!(!is_ripe(
+            crop,
+            &Block {
+                state: HashMap::new(),
+                ..wheat(7)
+            }
+        ));
+    }
+
+    #[test]
+    fn the_plot_is_walked_in_rows() {
+        let farm = CropFarm {
+            a: Position { x: 1, y: 64, z: 1 },
+            b: Position { x: 0, y: 64, z: 0 },
+            chest: Position { x: -1, y: 63, z: 0 },
+            seed: "minecraft:wheat_seeds".to_string(),
+            patrol_interval: 60,
+        };
+
+        let cells: Vec<_> = plan_patrol(&farm)
+            .into_iter()
+            .filter_map(|a| match a {
+                QueuedAction::TendCrop { pos, .. } => Some((pos.x, pos.z)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(cells, vec![(0, 0), (1, 0), (1, 1), (0, 1)]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+
+use models::Slot;
+
+use crate::config::Config;
+
+/// Slots named in `reserved_slots`. Deposit and junk dropping leave them
+/// alone.
+pub fn reserved(config: &Config) -> HashSet<i64> {
+    config.reserved_slots.values().copied().collect()
+}
+
+/// Unreserved slots holding an item from the junk list.
+pub fn junk_slots(slots: &[Slot], config: &Config) -> Vec<i64> {
+    let reserved = reserved(config);
+    slots
+        .iter()
+        .filter(|s| !reserved.contains(&s.id))
+        .filter(|s| {
+            s.type_field
+                .as_ref()
+                .is_some_and(|t| config.junk_items.contains(&t.name))
+        })
+        .map(|s| s.id)
+        .collect()
+}
+
+/// Transfers, as `(from, to)` slot pairs, that merge partial stacks of the
+/// same item. Later slots are emptied into earlier ones, reserved slots are
+/// left out.
+pub fn merges(slots: &[Slot], reserved: &HashSet<i64>) -> Vec<(i64, i64)> {
+    let mut partial: Vec<(i64, &str, i64, i64)> = slots
+        .iter()
+        .filter(|s| !reserved.contains(&s.id) && s.space > 0)
+        .filter_map(|s| {
+            let item = s.type_field.as_ref()?;
+            Some((s.id, item.name.as_str(), item.count, s.space))
+        })
+        .collect();
+    partial.sort_by_key(|(id, ..)| *id);
+
+    let mut moves = Vec::new();
+    for to in 0..partial.len() {
+        for from in (to + 1..partial.len()).rev() {
+            let (to_id, name, held, space) = partial[to];
+            let (from_id, other, count, _) = partial[from];
+            // a slot emptied by an earlier merge stays empty
+            if name != other || held == 0 || space == 0 || count == 0 {
+                continue;
+            }
+
+            let moved = space.min(count);
+            partial[to].2 += moved;
+            partial[to].3 -= moved;
+            partial[from].2 -= moved;
+            partial[from].3 += moved;
+            moves.push((from_id, to_id));
+        }
+    }
+
+    moves
+}
+
+/// What to store from unreserved slots while keeping up to the given count
+/// of each item in `keep`, as `(slot, count)`. A count of `None` stores the
+/// whole slot.
+pub fn surplus(
+    slots: &[Slot],
+    reserved: &HashSet<i64>,
+    keep: &[(String, i64)],
+) -> Vec<(i64, Option<i64>)> {
+    let mut allowance: HashMap<&str, i64> = keep
+        .iter()
+        .map(|(item, count)| (item.as_str(), *count))
+        .collect();
+
+    let mut stored = Vec::new();
+    for slot in slots.iter().filter(|s| !reserved.contains(&s.id)) {
+        let Some(item) = &slot.type_field else {
+            continue;
+        };
+
+        let kept = match allowance.get_mut(item.name.as_str()) {
+            Some(left) => {
+                let kept = item.count.min(*left);
+                *left -= kept;
+                kept
+            }
+            None => 0,
+        };
+        match kept {
+            0 => stored.push((slot.id, None)),
+            kept if kept < item.count => stored.push((slot.id, Some(item.count - kept))),
+            _ => {}
+        }
+    }
+
+    stored
+}
+
+/// Whether dropping into `block` puts the items into its inventory rather
+/// than on the ground.
+pub fn is_container(block: &str) -> bool {
+    block.ends_with("chest")
+        || block.ends_with("barrel")
+        || block.ends_with("shulker_box")
+        || block.ends_with("hopper")
+        || block.starts_with("computercraft:turtle")
+}
+
+/// How many of `item` the unreserved slots hold.
+pub fn count(slots: &[Slot], reserved: &HashSet<i64>, item: &str) -> i64 {
+    slots
+        .iter()
+        .filter(|s| !reserved.contains(&s.id))
+        .filter_map(|s| s.type_field.as_ref())
+        .filter(|t| t.name == item)
+        .map(|t| t.count)
+        .sum()
+}
+
+/// Items other than `item`, and anything in reserved slots, that slots
+/// gained between `before` and `after`, as `(slot, count)`. Sucking from a
+/// chest takes whatever stack comes first into whatever slot is free, these
+/// are the ones to put back.
+pub fn gained_besides(
+    before: &[Slot],
+    after: &[Slot],
+    reserved: &HashSet<i64>,
+    item: &str,
+) -> Vec<(i64, i64)> {
+    let held = |slots: &[Slot], id: i64| {
+        slots
+            .iter()
+            .find(|s| s.id == id)
+            .and_then(|s| s.type_field.as_ref())
+            .map_or(0, |t| t.count)
+    };
+
+    after
+        .iter()
+        .filter_map(|s| {
+            let t = s.type_field.as_ref()?;
+            let gained = t.count - held(before, s.id);
+            let unwanted = t.name != item || reserved.contains(&s.id);
+            (unwanted && gained > 0).then_some((s.id, gained))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use models::Type;
+
+    use super::*;
+
+    fn slot(id: i64, name: &str, count: i64) -> Slot {
+        Slot {
+            id,
+            type_field: Some(Type {
+                name: name.to_string(),
+                count,
+            }),
+            space: 64 - count,
+        }
+    }
+
+    #[test]
+    fn junk_in_reserved_slots_is_kept() {
+        let config = Config {
+            reserved_slots: HashMap::from([("filler".to_string(), 16)]),
+            ..Config::default()
+        };
+        let slots = [
+            slot(1, "minecraft:cobblestone", 64),
+            slot(2, "minecraft:diamond", 3),
+            slot(16, "minecraft:cobblestone", 64),
+        ];
+
+        assert_eq!(junk_slots(&slots, &config), vec![1]);
+    }
+
+    #[test]
+    fn partial_stacks_merge_into_the_first_slot() {
+        let slots = [
+            slot(1, "minecraft:raw_iron", 40),
+            slot(2, "minecraft:coal", 10),
+            slot(3, "minecraft:raw_iron", 30),
+            slot(4, "minecraft:raw_iron", 5),
+            slot(5, "minecraft:coal", 60),
+        ];
+
+        assert_eq!(
+            merges(&slots, &HashSet::new()),
+            vec![(4, 1), (3, 1), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn surplus_keeps_the_wanted_amount() {
+        let slots = [
+            slot(1, "minecraft:wheat_seeds", 40),
+            slot(2, "minecraft:wheat", 12),
+            slot(3, "minecraft:wheat_seeds", 40),
+            slot(4, "minecraft:wheat_seeds", 10),
+        ];
+        let keep = [("minecraft:wheat_seeds".to_string(), 64)];
+
+        assert_eq!(
+            surplus(&slots, &HashSet::new(), &keep),
+            vec![(2, None), (3, Some(16)), (4, None)]
+        );
+    }
+
+    #[test]
+    fn other_items_picked_up_are_found() {
+        let before = [slot(1, "minecraft:iron_ingot", 10)];
+        let after = [
+            slot(1, "minecraft:iron_ingot", 64),
+            slot(2, "minecraft:iron_ingot", 6),
+            slot(3, "minecraft:sand", 32),
+        ];
+
+        assert_eq!(count(&after, &HashSet::new(), "minecraft:iron_ingot"), 70);
+        assert_eq!(
+            gained_besides(&before, &after, &HashSet::new(), "minecraft:iron_ingot"),
+            vec![(3, 32)]
+        );
+    }
+
+    #[test]
+    fn items_sucked_into_reserved_slots_are_put_back() {
+        let reserved = HashSet::from([2]);
+        let before = [slot(1, "minecraft:iron_ingot", 10)];
+        let after = [
+            slot(1, "minecraft:iron_ingot", 64),
+            slot(2, "minecraft:iron_ingot", 6),
+        ];
+
+        assert_eq!(count(&after, &reserved, "minecraft:iron_ingot"), 64);
+        assert_eq!(
+            gained_besides(&before, &after, &reserved, "minecraft:iron_ingot"),
+            vec![(2, 6)]
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use models::{Job, JobKind, JobStatus, Position, Progress, QuarryOrder, QueuedAction};
+
+use crate::quarry;
+use crate::schematic;
+
+/// Failures after which a split job stays failed instead of being handed to
+/// another turtle.
+const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum JobError {
+    NotFound(u64),
+    InvalidTransition { from: JobStatus, to: JobStatus },
+}
+
+/// Work units an action counts for in a job's progress.
+fn work(action: &QueuedAction) -> u64 {
+    match action {
+        QueuedAction::DigColumn { up, down, .. } => 1 + *up as u64 + *down as u64,
+        QueuedAction::Fetch { count, .. } => *count as u64,
+        QueuedAction::Restock(_) => 0,
+        _ => 1,
+    }
+}
+
+fn plan(kind: &JobKind) -> VecDeque<QueuedAction> {
+    match kind {
+        JobKind::Quarry { a, b, order } => quarry::plan(*a, *b, *order),
+        JobKind::Deposit => VecDeque::from([QueuedAction::Deposit]),
+        JobKind::Fetch {
+            item,
+            count,
+            destination,
+        } => VecDeque::from([QueuedAction::Fetch {
+            item: item.clone(),
+            count: *count,
+            destination: *destination,
+        }]),
+        JobKind::Build {
+            schematic,
+            origin,
+            rotation,
+        } => schematic::plan(schematic, *origin, *rotation),
+    }
+}
+
+/// Every job the server knows about.
+#[derive(Debug, Default)]
+pub struct JobBoard {
+    jobs: BTreeMap<u64, Job>,
+    next_id: u64,
+    dirty: HashSet<u64>,
+}
+
+impl JobBoard {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Restores jobs loaded from storage without marking them dirty. The
+    /// actions still to run are planned again and skipped up to the job's
+    /// step.
+    pub fn load(&mut self, mut job: Job) {
+        let plan = plan(&job.kind);
+        if job.checkpoint.is_empty() {
+            job.checkpoint = plan.into_iter().skip(job.step as usize).collect();
+        } else {
+            // saved with its checkpoint, before steps were stored
+            job.step = plan.len().saturating_sub(job.checkpoint.len()) as u64;
+        }
+
+        self.next_id = self.next_id.max(job.id + 1);
+        self.jobs.insert(job.id, job);
+    }
+
+    pub fn create(&mut self, owner: Option<usize>, kind: JobKind, group: Option<u64>) -> Job {
+        let checkpoint = plan(&kind);
+        let job = Job {
+            id: self.next_id,
+            owner,
+            group,
+            attempts: 0,
+            kind,
+            status: JobStatus::Pending,
+            progress: Progress {
+                done: 0,
+                total: checkpoint.iter().map(work).sum(),
+                skipped: 0,
+            },
+            checkpoint,
+            step: 0,
+            message: None,
+        };
+        self.next_id += 1;
+
+        tracing::info!(
+            "created job {} for turtle {:?}: {:?}",
+            job.id,
+            owner,
+            job.kind
+        );
+        self.jobs.insert(job.id, job.clone());
+        self.dirty.insert(job.id);
+
+        job
+    }
+
+    /// Splits a quarry into `parts` unassigned jobs sharing a group id, for
+    /// the scheduler to hand out to idle turtles.
+    pub fn create_split(
+        &mut self,
+        a: Position,
+        b: Position,
+        order: QuarryOrder,
+        parts: usize,
+    ) -> Vec<Job> {
+        let group = self.next_id;
+        quarry::split(a, b, parts)
+            .into_iter()
+            .map(|(a, b)| self.create(None, JobKind::Quarry { a, b, order }, Some(group)))
+            .collect()
+    }
+
+    /// Cells job `id` already built, which paths must not dig through.
+    pub fn built(&self, id: u64) -> HashSet<Position> {
+        let Some(Job {
+            kind:
+                JobKind::Build {
+                    schematic,
+                    origin,
+                    rotation,
+                },
+            checkpoint,
+            ..
+        }) = self.jobs.get(&id)
+        else {
+            return HashSet::new();
+        };
+
+        let left: HashSet<Position> = checkpoint
+            .iter()
+            .filter_map(|a| match a {
+                QueuedAction::PlaceBlock { pos, .. } => Some(*pos),
+                _ => None,
+            })
+            .collect();
+        schematic::placements(schematic, *origin, *rotation)
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .filter(|pos| !left.contains(pos))
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.values().cloned().collect()
+    }
+
+    pub fn pause(&mut self, id: u64) -> Result<Job, JobError> {
+        self.transition(
+            id,
+            JobStatus::Paused,
+            &[JobStatus::Pending, JobStatus::Running],
+        )
+    }
+
+    /// Queues a paused or failed job again; it continues from its checkpoint.
+    pub fn resume(&mut self, id: u64) -> Result<Job, JobError> {
+        self.transition(
+            id,
+            JobStatus::Pending,
+            &[JobStatus::Paused, JobStatus::Failed],
+        )
+    }
+
+    /// Pauses a job that cannot go on without help, saying what it needs.
+    /// Resuming it retries the step it stopped at.
+    pub fn wait_for(&mut self, id: u64, message: String) {
+        if self.pause(id).is_err() {
+            return;
+        }
+
+        tracing::warn!("job {} is waiting: {}", id, message);
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.message = Some(message);
+        }
+    }
+
+    pub fn cancel(&mut self, id: u64) -> Result<Job, JobError> {
+        self.transition(
+            id,
+            JobStatus::Cancelled,
+            &[
+                JobStatus::Pending,
+                JobStatus::Running,
+                JobStatus::Paused,
+                JobStatus::Failed,
+            ],
+        )
+    }
+
+    fn transition(
+        &mut self,
+        id: u64,
+        to: JobStatus,
+        allowed_from: &[JobStatus],
+    ) -> Result<Job, JobError> {
+        let job = self.jobs.get_mut(&id).ok_or(JobError::NotFound(id))?;
+        if !allowed_from.contains(&job.status) {
+            return Err(JobError::InvalidTransition {
+                from: job.status,
+                to,
+            });
+        }
+
+        tracing::info!("job {}: {:?} -> {:?}", id, job.status, to);
+        job.status = to;
+        self.dirty.insert(id);
+
+        Ok(job.clone())
+    }
+
+    /// The next action for `owner`: from its running job, or else from its
+    /// oldest pending job, which then becomes running.
+    pub fn next_action(&mut self, owner: usize) -> Option<(u64, QueuedAction)> {
+        let owned = || self.jobs.values().filter(|j| j.owner == Some(owner));
+        let id = owned()
+            .find(|j| j.status == JobStatus::Running)
+            .or_else(|| owned().find(|j| j.status == JobStatus::Pending))?
+            .id;
+        let job = self.jobs.get_mut(&id)?;
+
+        if job.status == JobStatus::Pending {
+            tracing::info!("turtle {} starting job {}", owner, job.id);
+            job.status = JobStatus::Running;
+            self.dirty.insert(job.id);
+        }
+
+        match job.checkpoint.front() {
+            Some(action) => Some((job.id, action.clone())),
+            None => {
+                job.status = JobStatus::Done;
+                self.dirty.insert(job.id);
+                None
+            }
+        }
+    }
+
+    /// Records that the action at the front of job `id` finished.
+    pub fn complete_step(&mut self, id: u64) {
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return;
+        };
+
+        if let Some(action) = job.checkpoint.pop_front() {
+            job.progress.done += work(&action);
+            job.step += 1;
+        }
+        if job.checkpoint.is_empty() && job.status == JobStatus::Running {
+            tracing::info!("job {} done", id);
+            job.status = JobStatus::Done;
+        }
+        self.dirty.insert(id);
+    }
+
+    /// Records that the fetch at the front of job `id` delivered
+    /// `delivered` items. Progress counts items, so a short delivery leaves
+    /// the job done but incomplete, with a message saying what was missing.
+    pub fn complete_fetch(&mut self, id: u64, delivered: i64) {
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return;
+        };
+
+        if let Some(QueuedAction::Fetch { item, count, .. }) = job.checkpoint.front() {
+            if delivered < *count {
+                let message = format!("only {} of {} {} in stock", delivered, count, item);
+                tracing::warn!("job {}: {}", id, message);
+                job.message = Some(message);
+            }
+        }
+        if let Some(action) = job.checkpoint.pop_front() {
+            job.progress.done += (delivered.max(0) as u64).min(work(&action));
+            job.step += 1;
+        }
+        if job.checkpoint.is_empty() && job.status == JobStatus::Running {
+            tracing::info!("job {} done", id);
+            job.status = JobStatus::Done;
+        }
+        self.dirty.insert(id);
+    }
+
+    pub fn fail(&mut self, id: u64, message: String) {
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return;
+        };
+
+        tracing::warn!("job {} failed: {}", id, message);
+        job.attempts += 1;
+        job.message = Some(message);
+        if job.group.is_some() && job.attempts < MAX_ATTEMPTS {
+            // another turtle may get further from a different side
+            job.owner = None;
+            job.status = JobStatus::Pending;
+        } else {
+            job.status = JobStatus::Failed;
+        }
+        self.dirty.insert(id);
+    }
+
+    /// Whether `owner` has a job it is working on or about to start.
+    pub fn is_busy(&self, owner: usize) -> bool {
+        self.jobs.values().any(|j| {
+            j.owner == Some(owner) && matches!(j.status, JobStatus::Pending | JobStatus::Running)
+        })
+    }
+
+    /// Ids of pending jobs waiting for a turtle, oldest first.
+    pub fn unassigned(&self) -> Vec<u64> {
+        self.jobs
+            .values()
+            .filter(|j| j.owner.is_none() && j.status == JobStatus::Pending)
+            .map(|j| j.id)
+            .collect()
+    }
+
+    pub fn assign(&mut self, id: u64, owner: usize) {
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return;
+        };
+
+        tracing::info!("assigning job {} to turtle {}", id, owner);
+        job.owner = Some(owner);
+        self.dirty.insert(id);
+    }
+
+    /// Takes the unfinished split jobs away from `owner` so other turtles can
+    /// continue them from their checkpoints.
+    pub fn release(&mut self, owner: usize) {
+        for job in self.jobs.values_mut() {
+            if job.owner != Some(owner)
+                || job.group.is_none()
+                || !matches!(job.status, JobStatus::Pending | JobStatus::Running)
+            {
+                continue;
+            }
+
+            tracing::info!("releasing job {} from turtle {}", job.id, owner);
+            job.owner = None;
+            job.status = JobStatus::Pending;
+            self.dirty.insert(job.id);
+        }
+    }
+
+    /// Jobs changed since the last call, for writing to storage.
+    pub fn take_dirty(&mut self) -> Vec<Job> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty
+            .into_iter()
+            .filter_map(|id| self.jobs.get(&id).cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::{Rotation, Schematic, SchematicBlock};
+
+    use super::*;
+
+    const A: Position = Position { x: 0, y: 64, z: 0 };
+    const B: Position = Position { x: 3, y: 60, z: 3 };
+
+    fn quarry() -> JobKind {
+        JobKind::Quarry {
+            a: A,
+            b: B,
+            order: QuarryOrder::TopDown,
+        }
+    }
+
+    #[test]
+    fn a_job_is_paused_resumed_and_cancelled() {
+        let mut board = JobBoard::new();
+        let job = board.create(Some(1), quarry(), None);
+        assert_eq!(job.status, JobStatus::Pending);
+
+        assert!(board.next_action(1).is_some());
+        assert_eq!(board.get(job.id).unwrap().status, JobStatus::Running);
+
+        assert_eq!(board.pause(job.id).unwrap().status, JobStatus::Paused);
+        assert!(board.next_action(1).is_none());
+        assert!(!board.is_busy(1));
+
+        assert_eq!(board.resume(job.id).unwrap().status, JobStatus::Pending);
+        assert!(board.is_busy(1));
+
+        assert_eq!(board.cancel(job.id).unwrap().status, JobStatus::Cancelled);
+        assert!(board.next_action(1).is_none());
+    }
+
+    #[test]
+    fn invalid_transitions_are_refused() {
+        let mut board = JobBoard::new();
+        let job = board.create(Some(1), JobKind::Deposit, None);
+
+        assert_eq!(
+            board.resume(job.id),
+            Err(JobError::InvalidTransition {
+                from: JobStatus::Pending,
+                to: JobStatus::Pending,
+            })
+        );
+
+        board.cancel(job.id).unwrap();
+        let cases = [
+            (board.pause(job.id), JobStatus::Paused),
+            (board.resume(job.id), JobStatus::Pending),
+            (board.cancel(job.id), JobStatus::Cancelled),
+        ];
+        for (result, to) in cases {
+            assert_eq!(
+                result,
+                Err(JobError::InvalidTransition {
+                    from: JobStatus::Cancelled,
+                    to,
+                })
+            );
+        }
+
+        assert_eq!(board.pause(99), Err(JobError::NotFound(99)));
+    }
+
+    #[test]
+    fn a_failing_split_job_is_retried_until_max_attempts() {
+        let mut board = JobBoard::new();
+        let job = board.create_split(A, B, QuarryOrder::TopDown, 1).remove(0);
+
+        for attempt in 1..MAX_ATTEMPTS {
+            board.assign(job.id, 1);
+            board.fail(job.id, "ErrorNoPath".to_string());
+
+            let failed = board.get(job.id).unwrap();
+            assert_eq!(failed.attempts, attempt);
+            assert_eq!(failed.status, JobStatus::Pending);
+            assert_eq!(failed.owner, None);
+            assert_eq!(board.unassigned(), vec![job.id]);
+        }
+
+        board.assign(job.id, 1);
+        board.fail(job.id, "ErrorNoPath".to_string());
+
+        let failed = board.get(job.id).unwrap();
+        assert_eq!(failed.attempts, MAX_ATTEMPTS);
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert!(board.unassigned().is_empty());
+    }
+
+    #[test]
+    fn a_failing_job_of_its_own_fails_at_once() {
+        let mut board = JobBoard::new();
+        let job = board.create(Some(1), quarry(), None);
+
+        board.fail(job.id, "ErrorBlock".to_string());
+
+        let failed = board.get(job.id).unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.message.as_deref(), Some("ErrorBlock"));
+    }
+
+    #[test]
+    fn built_cells_are_the_placements_done_so_far() {
+        let schematic = Schematic {
+            palette: vec!["minecraft:stone".to_string()],
+            blocks: vec![
+                SchematicBlock {
+                    x: 0,
+                    y: 0,
+                    z: 0,
+                    block: 0,
+                },
+                SchematicBlock {
+                    x: 1,
+                    y: 0,
+                    z: 0,
+                    block: 0,
+                },
+            ],
+        };
+        let mut board = JobBoard::new();
+        let job = board.create(
+            Some(1),
+            JobKind::Build {
+                schematic,
+                origin: A,
+                rotation: Rotation::None,
+            },
+            None,
+        );
+        assert!(board.built(job.id).is_empty());
+
+        // the restock, then the first block
+        board.next_action(1);
+        board.complete_step(job.id);
+        board.complete_step(job.id);
+
+        assert_eq!(board.built(job.id), HashSet::from([A]));
+    }
+
+    #[test]
+    fn a_loaded_job_is_planned_again_from_its_step() {
+        let mut board = JobBoard::new();
+        let job = board.create(Some(1), quarry(), None);
+        board.next_action(1);
+        board.complete_step(job.id);
+        board.complete_step(job.id);
+        let saved = Job {
+            checkpoint: VecDeque::new(),
+            ..board.get(job.id).unwrap().clone()
+        };
+
+        let mut restored = JobBoard::new();
+        restored.load(saved);
+
+        let restored = restored.get(job.id).unwrap();
+        assert_eq!(restored.step, 2);
+        assert_eq!(restored, board.get(job.id).unwrap());
+    }
+}
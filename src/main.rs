@@ -1,32 +1,54 @@
-use ::models::{
-    Action, ActionType, Direction, Goal, Material, MoveDirection, Position, QueuedAction, Turtle,
-    TurtleCommand, TurtleError,
-};
+use ::models::{Action, ActionType, Goal, Handshake, QueuedAction, TurtleCommand, TurtleError};
 use axum::{
     extract::{
-        ws::{Message, WebSocketUpgrade},
+        ws::{Message, WebSocket, WebSocketUpgrade},
         State,
     },
     response::IntoResponse,
-    routing::get,
+    routing::{get, post},
     Router,
 };
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{stream::SplitStream, SinkExt, StreamExt};
 use models::TurtleController;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
 use std::{
-    collections::{HashSet, VecDeque},
     net::SocketAddr,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use crate::models::TurtleManager;
-
+use crate::config::Config;
+use crate::jobs::JobBoard;
+use crate::models::{Session, TurtleManager};
+use crate::storage::Storage;
+use crate::transport::WebsocketTransport;
+
+mod api;
+mod chests;
+mod client;
+mod config;
+mod farm;
+mod inventory;
+mod jobs;
+mod mining;
 mod models;
+mod pathfinding;
+mod protocol;
+mod quarry;
+mod reservations;
+mod schematic;
+mod storage;
+mod transport;
+mod trees;
+mod world;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+const REFUEL_RETRY: Duration = Duration::from_secs(30);
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() {
@@ -38,10 +60,57 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let manager = Arc::new(models::TurtleManager::new());
+    let config = Arc::new(Config::load());
+    let manager = match std::env::var("DATABASE_URL") {
+        Ok(url) => {
+            let storage = Storage::connect(&url)
+                .await
+                .expect("failed to connect to postgres");
+            Arc::new(
+                TurtleManager::load(storage, config)
+                    .await
+                    .expect("failed to load turtles"),
+            )
+        }
+        Err(_) => {
+            tracing::warn!("DATABASE_URL is not set, fleet state will not be saved");
+            Arc::new(TurtleManager::new(config))
+        }
+    };
+
+    let m = manager.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SAVE_INTERVAL).await;
+            m.save_all().await;
+        }
+    });
+
+    let m = manager.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SCHEDULE_INTERVAL).await;
+            m.schedule().await;
+        }
+    });
+
     let app = Router::new()
         .route("/ws", get(handle_connection))
+        .route("/client.lua", get(client::client_script))
+        .route("/install.lua", get(client::install_script))
         .route("/turtle_updates", get(handle_turtle_updates))
+        .route("/jobs", get(api::list_jobs).post(api::create_job))
+        .route("/jobs/split", post(api::split_quarry))
+        .route("/jobs/fetch", post(api::fetch_items))
+        .route("/jobs/build", post(api::build_structure))
+        .route("/schematics/materials", post(api::schematic_materials))
+        .route("/jobs/:id", get(api::get_job))
+        .route("/jobs/:id/pause", post(api::pause_job))
+        .route("/jobs/:id/resume", post(api::resume_job))
+        .route("/jobs/:id/cancel", post(api::cancel_job))
+        .route("/chests", get(api::list_chests))
+        .route("/chests/find", get(api::find_item))
+        .route("/chests/totals", get(api::item_totals))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(DefaultMakeSpan::default().include_headers(true)),
@@ -116,128 +185,248 @@ async fn handle_turtle_updates(
 
 async fn event_loop(
     turtle_controller: Arc<tokio::sync::Mutex<TurtleController>>,
+    jobs: Arc<tokio::sync::Mutex<JobBoard>>,
 ) -> Result<(), TurtleError> {
-    turtle_controller.lock().await.reset_north().await.unwrap();
+    {
+        // a reconnecting turtle that still knows its heading carries on
+        // facing the way it was
+        let mut tc = turtle_controller.lock().await;
+        if !tc.turtle.lock().await.heading_known {
+            tc.reset_north().await?;
+        }
+    }
+    let (refuel_threshold, can_deposit) = {
+        let tc = turtle_controller.lock().await;
+        (
+            tc.config.refuel_threshold,
+            tc.config.deposit_chest.is_some(),
+        )
+    };
     loop {
-        tracing::info!("hello world");
         let turtle = {
             let tc = turtle_controller.lock().await;
             let turtle = tc.turtle.lock().await.clone();
             turtle
         };
 
-        // if turtle.fuel < 50 {
-        //     tracing::info!("STOPPED MINING. NEEDS FUEL");
-        //     let tc = turtle_controller.lock().await;
-        //     tc.turtle.lock().await.curr_goal = Goal::Refuel;
-        // } else if turtle.main_goal != turtle.curr_goal && turtle.curr_goal != Goal::Deposit {
-        //     let tc = turtle_controller.lock().await;
-        //     tc.turtle.lock().await.curr_goal = turtle.main_goal;
-        // }
-        //
-        // if turtle.needs_deposit() {
-        //     let tc = turtle_controller.lock().await;
-        //     tc.turtle.lock().await.curr_goal = turtle.main_goal;
-        //     tc.turtle.lock().await.prev_dir = turtle.direction.clone();
-        //     tc.turtle.lock().await.prev_pos = turtle.pos;
-        //     tc.turtle.lock().await.curr_goal = Goal::Deposit;
-        // }
+        if turtle.curr_goal != Goal::Refuel && turtle.fuel < refuel_threshold {
+            tracing::info!(
+                "STOPPED {:?}. NEEDS FUEL ({})",
+                turtle.curr_goal,
+                turtle.fuel
+            );
+            let tc = turtle_controller.lock().await;
+            tc.turtle.lock().await.curr_goal = Goal::Refuel;
+            continue;
+        }
+
+        if turtle.needs_deposit() {
+            let mut tc = turtle_controller.lock().await;
+            tc.manage_inventory().await?;
+
+            let mut t = tc.turtle.lock().await;
+            if can_deposit && t.needs_deposit() {
+                tracing::info!("inventory full, depositing");
+                t.prev_dir = turtle.direction;
+                t.prev_pos = turtle.pos;
+                t.curr_goal = Goal::Deposit;
+                continue;
+            }
+        }
 
         match turtle.curr_goal {
             Goal::Idle => {
-                tracing::info!("sdfasfda");
                 if turtle.action_queue.len() > 0 {
-                    tracing::info!("fuck");
-                    let mut tc = turtle_controller.lock().await;
-                    let action = tc.turtle.lock().await.action_queue.pop_front().unwrap();
-
-                    tracing::info!("PROCESSING QA: {:?}", action);
-                    match action {
-                        QueuedAction::Turn(dir) => tc.turn(dir).await?,
-                        QueuedAction::MoveAndMineLen(l) => {
-                            tc.move_blocks(MoveDirection::Forward, l).await?
-                        }
-                        QueuedAction::MoveDirection(d) => {
-                            tc.turn_towards(d).await?;
-                            tc.move_and_mine_block(MoveDirection::Forward).await?;
-                        }
-                        QueuedAction::MovePoint(p) => tc.move_point(p).await?,
-                        QueuedAction::TurnToward(d) => tc.turn_towards(d).await?,
-                        QueuedAction::MoveAndMine(d) => tc.move_and_mine_block(d).await?,
-                        QueuedAction::Nothing => {}
-                    };
-                    drop(tc);
-
-                    let tc = turtle_controller.lock().await;
-                    tc.turtle.lock().await.executed_actions.push_back(action);
+                    run_next_action(&turtle_controller).await?;
+                } else {
+                    run_job_step(&turtle_controller, &jobs, turtle.id).await?;
                 }
 
                 let mut tc = turtle_controller.lock().await;
-                tc.send_command(Action {
-                    action: ActionType::Info,
-                })
-                .await?;
+                tc.send_command(Action::new(ActionType::Info)).await?;
             }
             Goal::Refuel => {
                 let mut tc = turtle_controller.lock().await;
-                tc.send_command(Action {
-                    action: ActionType::Info,
-                })
-                .await?;
-
-                let slot = turtle.slots.iter().find(|s| {
-                    let t = s.type_field.clone().unwrap();
+                if tc.refuel().await? {
+                    let mut turtle = tc.turtle.lock().await;
+                    tracing::info!(
+                        "refuelled to {}, resuming {:?}",
+                        turtle.fuel,
+                        turtle.main_goal
+                    );
+                    turtle.curr_goal = turtle.main_goal;
+                } else {
+                    drop(tc);
+                    tracing::warn!("no fuel available, retrying in {:?}", REFUEL_RETRY);
+                    tokio::time::sleep(REFUEL_RETRY).await;
+                }
+            }
+            Goal::Deposit => {
+                let mut tc = turtle_controller.lock().await;
+                tc.deposit().await?;
 
-                    if t.name == "minecraft:coal" {
-                        return true;
-                    }
+                let (prev_pos, prev_dir) = {
+                    let turtle = tc.turtle.lock().await;
+                    (turtle.prev_pos, turtle.prev_dir)
+                };
+                tc.move_point(prev_pos).await?;
+                tc.turn_towards(prev_dir).await?;
 
-                    if t.name == "minecraft:charcoal" {
-                        return true;
-                    }
+                let mut turtle = tc.turtle.lock().await;
+                tracing::info!("deposited, resuming {:?}", turtle.main_goal);
+                turtle.curr_goal = turtle.main_goal;
+            }
+            Goal::Mine(material) => {
+                if turtle.action_queue.is_empty() {
+                    let tc = turtle_controller.lock().await;
+                    let plan =
+                        mining::plan_strip(turtle.pos, turtle.direction, material, &tc.config);
+                    tc.turtle.lock().await.action_queue = plan;
+                }
 
-                    return false;
-                });
-
-                // if turtle.fuel > 50 {
-                //     turtle.curr_goal = turtle.main_goal;
-                // }
-                //
-                // if slot.is_none() {
-                //     turtle.set_level(Material::Coal as i64).await?;
-                //     turtle.move_turtle(MoveDirection::Forward).await?;
-                //
-                //     continue;
-                // }
-                if slot.is_some() {
-                    turtle_controller
-                        .lock()
-                        .await
-                        .send_command(Action {
-                            action: ActionType::Slot {
-                                name: "Select".to_string(),
-                                args: vec![slot.unwrap().id],
-                            },
-                        })
-                        .await?;
-                    turtle_controller
-                        .lock()
-                        .await
-                        .send_command(Action {
-                            action: ActionType::Refuel,
-                        })
-                        .await?;
+                run_next_action(&turtle_controller).await?;
+            }
+            Goal::TreeFarm | Goal::Farm => {
+                if turtle.action_queue.is_empty() {
+                    let tc = turtle_controller.lock().await;
+                    let patrol = match turtle.curr_goal {
+                        Goal::TreeFarm => tc.config.tree_farm.as_ref().map(trees::plan_patrol),
+                        _ => tc.config.crop_farm.as_ref().map(farm::plan_patrol),
+                    };
+                    let Some(patrol) = patrol else {
+                        tracing::warn!(
+                            "no farm for {:?} is configured, going idle",
+                            turtle.curr_goal
+                        );
+                        let mut turtle = tc.turtle.lock().await;
+                        turtle.main_goal = Goal::Idle;
+                        turtle.curr_goal = Goal::Idle;
+                        continue;
+                    };
+                    tc.turtle.lock().await.action_queue = patrol;
                 }
 
-                // if turtle.fuel > 50 {
-                //     let tc = turtle_controller.lock().await;
-                //     let mut turtle = tc.turtle.lock().await;
-                //     turtle.curr_goal = turtle.main_goal;
-                // }
-                tracing::info!("here...");
+                run_next_action(&turtle_controller).await?;
             }
-            Goal::Deposit => {}
-            Goal::Mine(_d) => {}
+        }
+    }
+}
+
+/// Runs the action at the front of the turtle's queue, if any. The action
+/// stays queued until it succeeded, so a session that fails or is taken over
+/// part way through leaves it for the next one. A client too old for the
+/// action drops its queue and goal instead.
+async fn run_next_action(
+    turtle_controller: &Arc<tokio::sync::Mutex<TurtleController>>,
+) -> Result<(), TurtleError> {
+    let mut tc = turtle_controller.lock().await;
+    let Some(action) = tc.turtle.lock().await.action_queue.front().cloned() else {
+        return Ok(());
+    };
+
+    tracing::info!("PROCESSING QA: {:?}", action);
+    if let Err(e) = tc.run_queued_action(&action).await {
+        let TurtleError::ErrorUnsupported(reason) = e else {
+            return Err(e);
+        };
+        let mut turtle = tc.turtle.lock().await;
+        tracing::warn!(
+            "turtle {} cannot run {:?} ({}), giving up {:?}",
+            turtle.id,
+            action,
+            reason,
+            turtle.main_goal
+        );
+        turtle.action_queue.clear();
+        turtle.main_goal = Goal::Idle;
+        turtle.curr_goal = Goal::Idle;
+        return Ok(());
+    }
+
+    let mut turtle = tc.turtle.lock().await;
+    // a quarry queues its plan ahead of itself and SetGoal empties the
+    // queue, so the action is not necessarily at the front any more
+    if let Some(i) = turtle.action_queue.iter().position(|a| *a == action) {
+        turtle.action_queue.remove(i);
+    }
+    turtle.record_executed(action);
+
+    Ok(())
+}
+
+/// Runs the next action of the turtle's current job, if it has one. The job
+/// only moves past the action once it succeeded, so a dropped connection
+/// retries it on reconnect.
+async fn run_job_step(
+    turtle_controller: &Arc<tokio::sync::Mutex<TurtleController>>,
+    jobs: &Arc<tokio::sync::Mutex<JobBoard>>,
+    turtle_id: usize,
+) -> Result<(), TurtleError> {
+    let (job_id, action, built) = {
+        let mut jobs = jobs.lock().await;
+        let Some((job_id, action)) = jobs.next_action(turtle_id) else {
+            return Ok(());
+        };
+        (job_id, action, jobs.built(job_id))
+    };
+
+    let mut tc = turtle_controller.lock().await;
+    tc.built = built;
+    tracing::info!("PROCESSING JOB {}: {:?}", job_id, action);
+    let result = match &action {
+        // a fetch reports how much it delivered, which may fall short
+        QueuedAction::Fetch {
+            item,
+            count,
+            destination,
+        } => tc.fetch(item, *count, *destination).await.map(Some),
+        _ => tc.run_queued_action(&action).await.map(|()| None),
+    };
+    tc.built.clear();
+    match result {
+        Ok(Some(delivered)) => jobs.lock().await.complete_fetch(job_id, delivered),
+        Ok(None) => jobs.lock().await.complete_step(job_id),
+        Err(TurtleError::ErrorMissingItems(missing)) => {
+            let missing: Vec<String> = missing
+                .iter()
+                .map(|(item, count)| format!("{} {}", count, item))
+                .collect();
+            jobs.lock()
+                .await
+                .wait_for(job_id, format!("needs {} from storage", missing.join(", ")));
+            return Ok(());
+        }
+        Err(
+            e @ (TurtleError::ErrorBlock
+            | TurtleError::ErrorNoPath
+            | TurtleError::ErrorUnsupported(_)),
+        ) => {
+            jobs.lock()
+                .await
+                .fail(job_id, format!("{:?} while running {:?}", e, action));
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
+
+    tc.turtle.lock().await.record_executed(action);
+
+    Ok(())
+}
+
+async fn read_handshake(ws_rx: &mut SplitStream<WebSocket>) -> Result<Handshake, TurtleError> {
+    loop {
+        let message = tokio::time::timeout(HANDSHAKE_TIMEOUT, ws_rx.next())
+            .await
+            .map_err(|_| TurtleError::ErrorHandshake("timed out".to_string()))?;
+
+        match message {
+            Some(Ok(Message::Text(text))) => {
+                return serde_json::from_str(&text)
+                    .map_err(|e| TurtleError::ErrorHandshake(e.to_string()));
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(_)) | None => return Err(TurtleError::ErrorWebsockets),
         }
     }
 }
@@ -249,47 +438,90 @@ async fn handle_connection(
     ws.on_upgrade(move |socket| async move {
         let (mut ws_tx, mut ws_rx) = socket.split();
 
+        let handshake = match read_handshake(&mut ws_rx).await {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                tracing::warn!("turtle handshake failed: {:?}", e);
+                let _ = ws_tx.send(Message::Close(None)).await;
+                return;
+            }
+        };
+        if !protocol::supported(handshake.version) {
+            let rejected = protocol::rejection(handshake.version);
+            tracing::warn!("refusing turtle {}: {}", handshake.id, rejected.reason);
+            if let Ok(text) = serde_json::to_string(&rejected) {
+                let _ = ws_tx.send(Message::Text(text)).await;
+            }
+            let _ = ws_tx.send(Message::Close(None)).await;
+            return;
+        }
+        tracing::info!(
+            "turtle {} ({:?}, {:?}, protocol v{}) connected",
+            handshake.id,
+            handshake.label,
+            handshake.kind,
+            handshake.version
+        );
+
         let (turtle_tx, mut turtle_rx) = unbounded_channel();
         let (response_tx, response_rx) = tokio::sync::mpsc::channel(32); // Create a channel for responses
 
-        let turtle = Arc::new(tokio::sync::Mutex::new(Turtle {
-            id: 1,
-            pos: Position { x: 0, y: 0, z: 0 },
-            fuel: 0,
-            slots: vec![],
-            blocks: vec![],
-            visited: HashSet::new(),
-            prev_pos: Position { x: 0, y: 0, z: 0 },
-            prev_dir: Direction::North,
-            direction: Direction::North,
-            curr_goal: Goal::Idle,
-            main_goal: Goal::Idle,
-            mine_area: vec![],
-            action_list: vec![],
-            action_queue: VecDeque::new(),
-            executed_actions: VecDeque::with_capacity(100),
-        }));
-
-        let turtle_controller =
-            TurtleController::new(turtle.clone(), turtle_tx.clone(), response_rx);
-
-        manager.add_turtle(turtle.clone()).await;
-
-        tokio::spawn(event_loop(Arc::new(tokio::sync::Mutex::new(
-            turtle_controller,
-        ))));
-
-        let response_tx_for_ws = response_tx.clone();
+        let (id, version) = (handshake.id, handshake.version);
+        let turtle = manager.connect(handshake).await;
+
+        let mut turtle_controller = TurtleController::new(
+            turtle,
+            manager.world.clone(),
+            manager.reservations.clone(),
+            manager.chests.clone(),
+            manager.config.clone(),
+            Box::new(WebsocketTransport::new(turtle_tx.clone(), response_rx)),
+        );
+        turtle_controller.protocol = version;
+
+        let m = manager.clone();
+        let session_tx = turtle_tx.clone();
+        let (registered_tx, registered_rx) = tokio::sync::oneshot::channel();
+        let event_loop = tokio::spawn(async move {
+            // wait until the old session stopped and this one is registered,
+            // so only one loop drives the turtle and `end_session` finds it
+            if registered_rx.await.is_err() {
+                return Ok(());
+            }
+            let result = event_loop(
+                Arc::new(tokio::sync::Mutex::new(turtle_controller)),
+                m.jobs.clone(),
+            )
+            .await;
+            if let Err(e) = &result {
+                tracing::warn!("turtle {} stopped: {:?}", id, e);
+            }
+            m.end_session(id, &session_tx).await;
+            result
+        });
+        manager
+            .start_session(
+                id,
+                Session {
+                    client_sender: turtle_tx,
+                    event_loop,
+                },
+            )
+            .await;
+        let _ = registered_tx.send(());
+
         tokio::spawn(async move {
             while let Some(message) = ws_rx.next().await {
                 match message {
                     Ok(msg) => {
                         if let Message::Text(text) = msg {
-                            response_tx_for_ws.send(text).await.unwrap(); // Send received messages to the response channel
+                            if response_tx.send(text).await.is_err() {
+                                break;
+                            }
                         }
                     }
-                    Err(_) => {
-                        println!("WebSocket error. Exiting.");
+                    Err(e) => {
+                        tracing::warn!("websocket error from turtle {}: {}", id, e);
                         break;
                     }
                 }
@@ -297,7 +529,235 @@ async fn handle_connection(
         });
 
         while let Some(update) = turtle_rx.recv().await {
+            let closing = matches!(update, Message::Close(_));
             let _ = ws_tx.send(update).await;
+            if closing {
+                return;
+            }
         }
+        let _ = ws_tx.send(Message::Close(None)).await;
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use ::models::{
+        Direction, InfoResp, JobKind, JobStatus, MineDirection, Position, QueuedAction, Rotation,
+        Schematic, SchematicBlock, Slot, TurnDirection, Type,
+    };
+
+    use super::*;
+    use crate::transport::mock::{self, reply, reset_north_script, ScriptedTransport};
+
+    const AFTER_RESET: Position = Position { x: 0, y: 0, z: -1 };
+
+    /// `mock::controller`, shared the way `event_loop` takes it.
+    fn controller(
+        script: Vec<(ActionType, InfoResp)>,
+    ) -> (Arc<tokio::sync::Mutex<TurtleController>>, ScriptedTransport) {
+        let (controller, transport) = mock::controller(script);
+        (Arc::new(tokio::sync::Mutex::new(controller)), transport)
+    }
+
+    fn jobs() -> Arc<tokio::sync::Mutex<JobBoard>> {
+        Arc::new(tokio::sync::Mutex::new(JobBoard::new()))
+    }
+
+    #[tokio::test]
+    async fn idle_turtle_runs_its_queue() {
+        let fuelled = reply(AFTER_RESET, 1000);
+        let mut script = reset_north_script(&fuelled);
+        script.push((ActionType::Turn(TurnDirection::Right), fuelled.clone()));
+        script.push((ActionType::Info, fuelled));
+        let (tc, remaining) = controller(script);
+        let turtle = tc.lock().await.turtle.clone();
+        turtle
+            .lock()
+            .await
+            .action_queue
+            .push_back(QueuedAction::TurnToward(Direction::East));
+
+        // the connection closes once the script runs out
+        let result = event_loop(tc, jobs()).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorWebsockets)));
+        let turtle = turtle.lock().await;
+        assert_eq!(turtle.direction, Direction::East);
+        assert!(turtle.action_queue.is_empty());
+        assert_eq!(
+            turtle.executed_actions.back(),
+            Some(&QueuedAction::TurnToward(Direction::East))
+        );
+        assert_eq!(remaining.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn only_recent_actions_are_remembered() {
+        let (tc, _) = controller(vec![]);
+        let turtle = tc.lock().await.turtle.clone();
+        {
+            let mut turtle = turtle.lock().await;
+            turtle.executed_actions = vec![QueuedAction::Wait(0); 100].into();
+            turtle.action_queue.push_back(QueuedAction::Nothing);
+        }
+
+        run_next_action(&tc).await.unwrap();
+
+        let turtle = turtle.lock().await;
+        assert_eq!(turtle.executed_actions.len(), 100);
+        assert_eq!(turtle.executed_actions.back(), Some(&QueuedAction::Nothing));
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_action_stays_queued() {
+        let (tc, _) = controller(vec![]);
+        let turtle = tc.lock().await.turtle.clone();
+        turtle
+            .lock()
+            .await
+            .action_queue
+            .push_back(QueuedAction::Wait(60));
+
+        let running = tokio::spawn(async move { run_next_action(&tc).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        running.abort();
+        let _ = running.await;
+
+        assert_eq!(
+            turtle.lock().await.action_queue.front(),
+            Some(&QueuedAction::Wait(60))
+        );
+    }
+
+    #[tokio::test]
+    async fn a_goal_the_client_is_too_old_for_is_dropped() {
+        let inspect = protocol::encode(ActionType::Inspect(MineDirection::Forward), 2);
+        let (tc, remaining) = controller(vec![(inspect, reply(Position::default(), 1000))]);
+        tc.lock().await.protocol = 2;
+        let turtle = tc.lock().await.turtle.clone();
+        {
+            let mut turtle = turtle.lock().await;
+            turtle.main_goal = Goal::TreeFarm;
+            turtle.curr_goal = Goal::TreeFarm;
+            turtle.action_queue.push_back(QueuedAction::TendTree {
+                spot: AFTER_RESET,
+                sapling: "minecraft:oak_sapling".to_string(),
+            });
+        }
+
+        run_next_action(&tc).await.unwrap();
+
+        let turtle = turtle.lock().await;
+        assert!(turtle.action_queue.is_empty());
+        assert_eq!(turtle.main_goal, Goal::Idle);
+        assert_eq!(turtle.curr_goal, Goal::Idle);
+        assert_eq!(remaining.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_known_heading_is_not_searched_for_again() {
+        let (tc, remaining) = controller(vec![(ActionType::Info, reply(AFTER_RESET, 1000))]);
+        let turtle = tc.lock().await.turtle.clone();
+        {
+            let mut turtle = turtle.lock().await;
+            turtle.heading_known = true;
+            turtle.fuel = 1000;
+        }
+
+        let result = event_loop(tc, jobs()).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorWebsockets)));
+        assert_eq!(remaining.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn idle_turtle_runs_its_job() {
+        let fuelled = reply(AFTER_RESET, 1000);
+        let mut script = reset_north_script(&fuelled);
+        script.push((ActionType::Info, fuelled));
+        let (tc, remaining) = controller(script);
+        let jobs = jobs();
+        let job = jobs.lock().await.create(Some(1), JobKind::Deposit, None);
+
+        let result = event_loop(tc, jobs.clone()).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorWebsockets)));
+        assert_eq!(
+            jobs.lock().await.get(job.id).unwrap().status,
+            JobStatus::Done
+        );
+        assert_eq!(remaining.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_job_short_of_materials_waits_for_them() {
+        let (tc, remaining) = controller(vec![]);
+        let jobs = jobs();
+        let schematic = Schematic {
+            palette: vec!["minecraft:stone".to_string()],
+            blocks: vec![SchematicBlock {
+                x: 0,
+                y: 0,
+                z: 0,
+                block: 0,
+            }],
+        };
+        let job = jobs.lock().await.create(
+            Some(1),
+            JobKind::Build {
+                schematic,
+                origin: AFTER_RESET,
+                rotation: Rotation::None,
+            },
+            None,
+        );
+
+        run_job_step(&tc, &jobs, 1).await.unwrap();
+
+        let jobs = jobs.lock().await;
+        let job = jobs.get(job.id).unwrap();
+        assert_eq!(job.status, JobStatus::Paused);
+        assert_eq!(
+            job.message.as_deref(),
+            Some("needs 1 minecraft:stone from storage")
+        );
+        assert!(matches!(
+            job.checkpoint.front(),
+            Some(QueuedAction::Restock(_))
+        ));
+        assert_eq!(remaining.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn low_fuel_switches_to_refuel_and_back() {
+        let coal = Slot {
+            id: 1,
+            type_field: Some(Type {
+                name: "minecraft:coal".to_string(),
+                count: 32,
+            }),
+            space: 32,
+        };
+        let empty = InfoResp {
+            slots: vec![coal],
+            ..reply(AFTER_RESET, 10)
+        };
+        let mut script = reset_north_script(&empty);
+        script.push((ActionType::Select(1), empty));
+        script.push((
+            ActionType::Refuel { count: Some(25) },
+            reply(AFTER_RESET, 2010),
+        ));
+        script.push((ActionType::Info, reply(AFTER_RESET, 2010)));
+        let (tc, remaining) = controller(script);
+        let turtle = tc.lock().await.turtle.clone();
+
+        let result = event_loop(tc, jobs()).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorWebsockets)));
+        let turtle = turtle.lock().await;
+        assert_eq!(turtle.curr_goal, Goal::Idle);
+        assert_eq!(turtle.fuel, 2010);
+        assert_eq!(remaining.remaining(), 0);
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+
+use models::{Direction, Material, Position, QueuedAction};
+
+use crate::config::Config;
+
+/// Plans the next stretch of a strip mine for `material`.
+///
+/// A turtle that is not at the material's level first goes there. Otherwise
+/// it digs `branch_spacing` blocks along `heading`, then a branch of
+/// `branch_length` blocks to the left and to the right, inspecting the walls
+/// after every step. Each stretch ends back in the main tunnel facing
+/// `heading`, so the next one carries on from there.
+pub fn plan_strip(
+    pos: Position,
+    heading: Direction,
+    material: Material,
+    config: &Config,
+) -> VecDeque<QueuedAction> {
+    let mut actions = VecDeque::new();
+
+    if pos.y != material.level() {
+        actions.push_back(QueuedAction::MovePoint(Position {
+            y: material.level(),
+            ..pos
+        }));
+        return actions;
+    }
+
+    let mut junction = pos;
+    for _ in 0..config.branch_spacing {
+        junction = junction.step(heading);
+        push_tunnel_step(&mut actions, heading);
+    }
+
+    for side in [heading.left(), heading.right()] {
+        for _ in 0..config.branch_length {
+            push_tunnel_step(&mut actions, side);
+        }
+        actions.push_back(QueuedAction::MovePoint(junction));
+    }
+    actions.push_back(QueuedAction::TurnToward(heading));
+
+    actions
+}
+
+fn push_tunnel_step(actions: &mut VecDeque<QueuedAction>, dir: Direction) {
+    actions.push_back(QueuedAction::MoveDirection(dir));
+    actions.push_back(QueuedAction::InspectWalls);
+}
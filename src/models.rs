@@ -1,20 +1,55 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use axum::extract::ws::Message;
-use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
 use models::*;
 
+use crate::chests::ChestIndex;
+use crate::config::Config;
+use crate::farm;
+use crate::inventory;
+use crate::jobs::JobBoard;
+use crate::pathfinding;
+use crate::protocol;
+use crate::quarry;
+use crate::reservations::{Claim, Reservations};
+use crate::storage::Storage;
+use crate::transport::Transport;
+use crate::trees;
+use crate::world::{CellState, WorldMap};
+
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_MOVE_ATTEMPTS: usize = 5;
+const MAX_REPLANS: usize = 10;
+const MAX_FUEL_WITHDRAWALS: usize = 4;
+const CLAIM_RETRY: Duration = Duration::from_millis(500);
+/// Claim attempts before giving up on a cell another turtle holds.
+const MAX_CLAIM_WAITS: usize = 40;
+/// Stacks picked up from one side when collecting drops.
+const MAX_PICKUPS: usize = 4;
+
 #[derive(Debug)]
 pub struct TurtleController {
     pub turtle: Arc<Mutex<models::Turtle>>,
-    pub client_sender: UnboundedSender<Message>,
-    pub response_receiver: Receiver<String>,
+    pub world: Arc<Mutex<WorldMap>>,
+    pub reservations: Arc<Mutex<Reservations>>,
+    pub chests: Arc<Mutex<ChestIndex>>,
+    pub config: Arc<Config>,
+    pub transport: Box<dyn Transport>,
+    pub command_timeout: Duration,
+    /// Protocol version the client announced in its handshake.
+    pub protocol: u32,
+    /// Cells the running job built, which paths go around instead of
+    /// digging through.
+    pub built: HashSet<Position>,
+    next_request_id: u64,
 }
 
 trait PopBackAdd<T> {
@@ -35,16 +70,203 @@ impl<T> PopBackAdd<T> for VecDeque<T> {
 impl TurtleController {
     pub fn new(
         turtle: Arc<Mutex<Turtle>>,
-        client_sender: UnboundedSender<Message>,
-        receiver: Receiver<String>,
+        world: Arc<Mutex<WorldMap>>,
+        reservations: Arc<Mutex<Reservations>>,
+        chests: Arc<Mutex<ChestIndex>>,
+        config: Arc<Config>,
+        transport: Box<dyn Transport>,
     ) -> Self {
         Self {
             turtle,
-            client_sender,
-            response_receiver: receiver,
+            world,
+            reservations,
+            chests,
+            config,
+            transport,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            protocol: PROTOCOL_VERSION,
+            built: HashSet::new(),
+            next_request_id: 0,
+        }
+    }
+
+    pub async fn run_queued_action(&mut self, action: &QueuedAction) -> Result<(), TurtleError> {
+        match *action {
+            QueuedAction::Turn(dir) => self.turn(dir).await?,
+            QueuedAction::MoveAndMineLen(l) => self.move_blocks(MoveDirection::Forward, l).await?,
+            QueuedAction::MoveDirection(d) => {
+                self.turn_towards(d).await?;
+                self.move_and_mine_block(MoveDirection::Forward).await?;
+            }
+            QueuedAction::MovePoint(p) => self.move_point(p).await?,
+            QueuedAction::TurnToward(d) => self.turn_towards(d).await?,
+            QueuedAction::MoveAndMine(d) => self.move_and_mine_block(d).await?,
+            QueuedAction::Mine(d) => {
+                self.dig(d).await?;
+            }
+            QueuedAction::InspectWalls => {
+                self.mine_vein().await?;
+            }
+            QueuedAction::Quarry { a, b, order } => {
+                let plan = quarry::plan(a, b, order);
+                let mut turtle = self.turtle.lock().await;
+                turtle.progress = Some(Progress {
+                    done: 0,
+                    total: quarry::volume(a, b),
+                    skipped: 0,
+                });
+                for action in plan.into_iter().rev() {
+                    turtle.action_queue.push_front(action);
+                }
+            }
+            QueuedAction::DigColumn { pos, up, down } => self.dig_column(pos, up, down).await?,
+            QueuedAction::Deposit => self.deposit().await?,
+            QueuedAction::Fetch {
+                ref item,
+                count,
+                destination,
+            } => {
+                let delivered = self.fetch(item, count, destination).await?;
+                if delivered < count {
+                    tracing::warn!("only {} of {} {} in stock", delivered, count, item);
+                }
+            }
+            QueuedAction::Restock(ref items) => self.restock(items).await?,
+            QueuedAction::TendTree { spot, ref sapling } => self.tend_tree(spot, sapling).await?,
+            QueuedAction::BurnFuel => {
+                if self.turtle.lock().await.fuel < self.config.refuel_threshold {
+                    self.refuel_from_inventory(self.config.refuel_target)
+                        .await?;
+                }
+            }
+            QueuedAction::Store { chest, ref keep } => self.store(chest, keep).await?,
+            QueuedAction::TendCrop { pos, ref seed } => self.tend_crop(pos, seed).await?,
+            QueuedAction::Wait(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+            QueuedAction::PlaceBlock { pos, ref item } => self.place_block(pos, item).await?,
+            QueuedAction::SetGoal(goal) => {
+                let mut turtle = self.turtle.lock().await;
+                turtle.action_queue.clear();
+                turtle.main_goal = goal;
+                turtle.curr_goal = goal;
+            }
+            QueuedAction::Nothing => {}
+        };
+
+        Ok(())
+    }
+
+    /// Mines out every target ore block connected to the turtle's cell,
+    /// checking all six neighbours of each ore it enters, then returns to
+    /// where it started facing the same way. Returns the number of ore
+    /// blocks mined.
+    pub async fn mine_vein(&mut self) -> Result<usize, TurtleError> {
+        let turtle = self.turtle.lock().await;
+        let (origin, heading) = (turtle.pos, turtle.direction);
+        let ores = self.target_ores(turtle.main_goal);
+        drop(turtle);
+
+        let mut visited = HashSet::from([origin]);
+        let mut looked = HashSet::new();
+        let mut path = vec![origin];
+        let mut mined = 0;
+
+        while let Some(here) = path.last().copied() {
+            match self.next_ore(here, &ores, &visited, &mut looked).await? {
+                Some((next, block)) => {
+                    visited.insert(next);
+                    let dir = self.prepare_step(next).await?;
+                    self.dig_and_move(dir).await?;
+                    path.push(next);
+                    mined += 1;
+
+                    let mut turtle = self.turtle.lock().await;
+                    tracing::info!("turtle {} mined {} at {:?}", turtle.id, block, next);
+                    if !turtle.ores_found.iter().any(|o| o.pos == next) {
+                        turtle.ores_found.push(OreSighting { pos: next, block });
+                    }
+                }
+                None => {
+                    path.pop();
+                    if let Some(prev) = path.last().copied() {
+                        let dir = self.prepare_step(prev).await?;
+                        self.dig_and_move(dir).await?;
+                    }
+                }
+            }
+        }
+
+        self.turn_towards(heading).await?;
+
+        Ok(mined)
+    }
+
+    /// Finds an unvisited block of one of `ores` next to `here`, turning to
+    /// look at horizontal neighbours the world map knows nothing about yet.
+    async fn next_ore(
+        &mut self,
+        here: Position,
+        ores: &[String],
+        visited: &HashSet<Position>,
+        looked: &mut HashSet<Position>,
+    ) -> Result<Option<(Position, String)>, TurtleError> {
+        loop {
+            let mut unknown = None;
+            {
+                let world = self.world.lock().await;
+                let neighbours = [
+                    (here.offset(0, 1, 0), None),
+                    (here.offset(0, -1, 0), None),
+                    (here.step(Direction::North), Some(Direction::North)),
+                    (here.step(Direction::East), Some(Direction::East)),
+                    (here.step(Direction::South), Some(Direction::South)),
+                    (here.step(Direction::West), Some(Direction::West)),
+                ];
+                for (pos, dir) in neighbours {
+                    if visited.contains(&pos) {
+                        continue;
+                    }
+                    match world.state(&pos) {
+                        CellState::Block(name) if ores.contains(&name) => {
+                            return Ok(Some((pos, name)));
+                        }
+                        CellState::Unknown if unknown.is_none() && !looked.contains(&pos) => {
+                            unknown = dir.map(|d| (pos, d));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            let Some((pos, dir)) = unknown else {
+                return Ok(None);
+            };
+            looked.insert(pos);
+            self.turn_towards(dir).await?;
         }
     }
 
+    /// Configured ore blocks a turtle with `goal` follows veins of: those
+    /// of the material it strip mines for, or all of them otherwise.
+    fn target_ores(&self, goal: Goal) -> Vec<String> {
+        self.config
+            .ore_blocks
+            .iter()
+            .filter(|name| match goal {
+                Goal::Mine(material) => name.ends_with(material.ore_suffix()),
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn ore_in_view(&self, turtle: &Turtle) -> bool {
+        let ores = self.target_ores(turtle.main_goal);
+        turtle
+            .blocks
+            .iter()
+            .any(|b| b.exists && b.block.as_ref().is_some_and(|name| ores.contains(name)))
+    }
+
     pub async fn move_blocks(
         &mut self,
         dir: MoveDirection,
@@ -86,14 +308,9 @@ impl TurtleController {
 
     pub async fn reset_north(&mut self) -> Result<(), TurtleError> {
         for _i in 0..4 {
-            self.send_command(Action {
-                action: ActionType::Mine(MineDirection::Forward),
-            })
-            .await?;
-            self.send_command(Action {
-                action: ActionType::Turn(TurnDirection::Right),
-            })
-            .await?;
+            self.dig(MineDirection::Forward).await?;
+            self.send_command(Action::new(ActionType::Turn(TurnDirection::Right)))
+                .await?;
         }
 
         let turtle = self.turtle.lock().await;
@@ -107,14 +324,16 @@ impl TurtleController {
             tracing::info!("curr_cords: {:?}", curr_cords.z);
             if curr_cords.z > turtle.pos.z {
                 tracing::info!("here");
+                drop(turtle);
+                let mut turtle = self.turtle.lock().await;
+                turtle.direction = Direction::North;
+                turtle.heading_known = true;
                 return Ok(());
             }
             drop(turtle);
             self.move_turtle(MoveDirection::Backward).await?;
-            self.send_command(Action {
-                action: ActionType::Turn(TurnDirection::Right),
-            })
-            .await?;
+            self.send_command(Action::new(ActionType::Turn(TurnDirection::Right)))
+                .await?;
         }
 
         Err(TurtleError::ErrorBlock)
@@ -137,254 +356,1193 @@ impl TurtleController {
 
         drop(turtle);
 
-        let _ = self
-            .send_command(Action {
-                action: ActionType::Turn(dir),
+        if let Err(e) = self.send_command(Action::new(ActionType::Turn(dir))).await {
+            // the turn may or may not have happened
+            self.turtle.lock().await.heading_known = false;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    pub async fn select(&mut self, slot: i64) -> Result<InfoResp, TurtleError> {
+        self.send_command(Action::new(ActionType::Select(slot)))
+            .await
+    }
+
+    /// Sends `action` and returns the result the client reported for it.
+    /// Clients older than protocol version 3 report none, which is an
+    /// `ErrorUnsupported` rather than a broken connection.
+    async fn run_for_result(&mut self, action: ActionType) -> Result<ActionResult, TurtleError> {
+        let resp = self.send_command(Action::new(action.clone())).await?;
+        resp.result.ok_or_else(|| {
+            TurtleError::ErrorUnsupported(format!(
+                "no result for {:?} from a protocol v{} client",
+                action, self.protocol
+            ))
+        })
+    }
+
+    async fn outcome(&mut self, action: ActionType) -> Result<Outcome, TurtleError> {
+        match self.run_for_result(action).await? {
+            ActionResult::Outcome(outcome) => Ok(outcome),
+            other => Err(TurtleError::ErrorParsing(format!(
+                "expected an outcome, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Places the selected item in `dir`.
+    pub async fn place(&mut self, dir: MineDirection) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Place(dir)).await
+    }
+
+    /// Drops `count` items of the selected slot in `dir`, the whole stack if
+    /// `None`. Dropping into a chest puts the items in it.
+    pub async fn drop_items(
+        &mut self,
+        dir: MineDirection,
+        count: Option<i64>,
+    ) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Drop {
+            direction: dir,
+            count,
+        })
+        .await
+    }
+
+    /// Picks up `count` items from the chest or ground in `dir`.
+    pub async fn suck_items(
+        &mut self,
+        dir: MineDirection,
+        count: Option<i64>,
+    ) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Suck {
+            direction: dir,
+            count,
+        })
+        .await
+    }
+
+    /// The block in `dir` with its state and tags, `None` for air.
+    pub async fn inspect(&mut self, dir: MineDirection) -> Result<Option<BlockState>, TurtleError> {
+        match self.run_for_result(ActionType::Inspect(dir)).await? {
+            ActionResult::Block(block) => Ok(block),
+            other => Err(TurtleError::ErrorParsing(format!(
+                "expected a block, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Whether the block in `dir` matches the selected item.
+    pub async fn compare(&mut self, dir: MineDirection) -> Result<bool, TurtleError> {
+        Ok(self.outcome(ActionType::Compare(dir)).await?.ok)
+    }
+
+    pub async fn attack(&mut self, dir: MineDirection) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Attack(dir)).await
+    }
+
+    /// Moves `count` items, or as many as fit, from the selected slot into
+    /// `slot`.
+    pub async fn transfer_to(
+        &mut self,
+        slot: i64,
+        count: Option<i64>,
+    ) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::TransferTo { slot, count }).await
+    }
+
+    /// The item in `slot`, or in the selected slot if `None`.
+    pub async fn item_detail(
+        &mut self,
+        slot: Option<i64>,
+        detailed: bool,
+    ) -> Result<Option<ItemDetail>, TurtleError> {
+        match self
+            .run_for_result(ActionType::GetItemDetail { slot, detailed })
+            .await?
+        {
+            ActionResult::Item(item) => Ok(item),
+            other => Err(TurtleError::ErrorParsing(format!(
+                "expected an item, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Swaps the selected item with the upgrade on `side`.
+    pub async fn equip(&mut self, side: Side) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Equip(side)).await
+    }
+
+    /// Crafts with the items in the inventory. Needs a crafting table
+    /// upgrade.
+    pub async fn craft(&mut self, limit: Option<i64>) -> Result<Outcome, TurtleError> {
+        self.outcome(ActionType::Craft(limit)).await
+    }
+
+    /// Tops fuel up to the configured target, from the inventory first and
+    /// from the fuel chest when that is not enough. Returns whether the
+    /// target was reached.
+    pub async fn refuel(&mut self) -> Result<bool, TurtleError> {
+        let target = self.config.refuel_target;
+        if self.refuel_from_inventory(target).await? {
+            return Ok(true);
+        }
+
+        let Some(chest) = self.config.fuel_chest else {
+            tracing::warn!("out of fuel items and no fuel chest is configured");
+            return Ok(false);
+        };
+
+        let turtle = self.turtle.lock().await;
+        let (prev_pos, prev_dir) = (turtle.pos, turtle.direction);
+        drop(turtle);
+
+        self.move_point(chest.offset(0, 1, 0)).await?;
+        for _ in 0..MAX_FUEL_WITHDRAWALS {
+            let before = self.turtle.lock().await.slots.clone();
+            self.send_command(Action::new(ActionType::Chest(ChestAction::Withdraw)))
+                .await?;
+            if self.refuel_from_inventory(target).await? {
+                break;
+            }
+            if self.turtle.lock().await.slots == before {
+                tracing::warn!("fuel chest at {:?} is empty", chest);
+                break;
+            }
+        }
+
+        self.move_point(prev_pos).await?;
+        self.turn_towards(prev_dir).await?;
+
+        Ok(self.turtle.lock().await.fuel >= target)
+    }
+
+    /// Goes to the deposit chest and empties every slot that is not reserved.
+    pub async fn deposit(&mut self) -> Result<(), TurtleError> {
+        let Some(chest) = self.config.deposit_chest else {
+            tracing::warn!("no deposit chest is configured");
+            return Ok(());
+        };
+
+        self.store(chest, &[]).await
+    }
+
+    /// Goes to `chest` and empties every slot that is not reserved, keeping
+    /// up to the given count of each item in `keep`.
+    pub async fn store(
+        &mut self,
+        chest: Position,
+        keep: &[(String, i64)],
+    ) -> Result<(), TurtleError> {
+        self.move_point(chest.offset(0, 1, 0)).await?;
+
+        let slots = self.turtle.lock().await.slots.clone();
+        let reserved = inventory::reserved(&self.config);
+        for (slot, count) in inventory::surplus(&slots, &reserved, keep) {
+            self.select(slot).await?;
+            let action = match count {
+                None => ActionType::Chest(ChestAction::Deposit),
+                Some(count) => ActionType::Drop {
+                    direction: MineDirection::Down,
+                    count: Some(count),
+                },
+            };
+            self.send_command(Action::new(action)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Collects `count` of `item` from the chests the index knows to hold it,
+    /// fullest first, and drops them into the chest at `destination`. Items
+    /// already carried, such as those picked up before a retried fetch was
+    /// interrupted, count towards `count`. Returns how many were delivered,
+    /// fewer than `count` when the stock runs short.
+    pub async fn fetch(
+        &mut self,
+        item: &str,
+        count: i64,
+        destination: Position,
+    ) -> Result<i64, TurtleError> {
+        let held = self.held(item).await;
+        let gathered = if held < count {
+            self.gather(item, count - held, Some(destination)).await?
+        } else {
+            0
+        };
+        let carried = (held + gathered).min(count);
+        if carried == 0 {
+            tracing::warn!("no {} in any known chest", item);
+            return Ok(0);
+        }
+
+        let mut left = carried;
+        self.move_point(destination.offset(0, 1, 0)).await?;
+
+        let reserved = inventory::reserved(&self.config);
+        let slots: Vec<(i64, i64)> = self
+            .turtle
+            .lock()
+            .await
+            .slots
+            .iter()
+            .filter(|s| !reserved.contains(&s.id))
+            .filter_map(|s| {
+                let t = s.type_field.as_ref()?;
+                (t.name == item).then_some((s.id, t.count))
             })
+            .collect();
+        for (slot, held) in slots {
+            if left <= 0 {
+                break;
+            }
+
+            let amount = held.min(left);
+            self.select(slot).await?;
+            if !self.drop_items(MineDirection::Down, Some(amount)).await?.ok {
+                tracing::warn!("chest at {:?} is full", destination);
+                break;
+            }
+            left -= amount;
+        }
+
+        Ok(carried - left)
+    }
+
+    /// Takes up to `count` of `item` from the chests the index knows to hold
+    /// it, fullest first, skipping the chest at `except`. Returns how many
+    /// were taken.
+    async fn gather(
+        &mut self,
+        item: &str,
+        count: i64,
+        except: Option<Position>,
+    ) -> Result<i64, TurtleError> {
+        let sources: Vec<Position> = self
+            .chests
+            .lock()
+            .await
+            .find(item)
+            .into_iter()
+            .filter(|l| l.name == item && Some(l.pos) != except)
+            .map(|l| l.pos)
+            .collect();
+
+        let start = self.held(item).await;
+        for source in sources {
+            let wanted = count - (self.held(item).await - start);
+            if wanted <= 0 {
+                break;
+            }
+            self.collect(source, item, wanted).await?;
+        }
+
+        Ok((self.held(item).await - start).min(count))
+    }
+
+    /// Gathers whatever the inventory lacks of `items` from storage. Fails
+    /// with the shortfall if storage does not have enough.
+    pub async fn restock(&mut self, items: &[(String, i64)]) -> Result<(), TurtleError> {
+        let mut missing = Vec::new();
+        for (item, count) in items {
+            let need = count - self.held(item).await;
+            if need <= 0 {
+                continue;
+            }
+
+            let got = self.gather(item, need, None).await?;
+            if got < need {
+                missing.push((item.clone(), need - got));
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(TurtleError::ErrorMissingItems(missing));
+        }
+        Ok(())
+    }
+
+    /// Places `item` at `pos` from the cell above, clearing whatever is in
+    /// the way first.
+    pub async fn place_block(&mut self, pos: Position, item: &str) -> Result<(), TurtleError> {
+        self.move_point(pos.offset(0, 1, 0)).await?;
+        if self.world.lock().await.state(&pos) != CellState::Air {
+            self.dig(MineDirection::Down).await?;
+        }
+
+        let Some(slot) = self.slot_with(item).await else {
+            return Err(TurtleError::ErrorMissingItems(vec![(item.to_string(), 1)]));
+        };
+
+        self.select(slot).await?;
+        let outcome = self.place(MineDirection::Down).await?;
+        if !outcome.ok {
+            tracing::warn!("could not place {} at {:?}: {:?}", item, pos, outcome.error);
+            return Err(TurtleError::ErrorBlock);
+        }
+
+        Ok(())
+    }
+
+    /// Chops the tree at `spot` if one has grown, then makes sure a sapling
+    /// is planted there and picks up what fell from the leaves. The turtle
+    /// works from the cell south of the spot.
+    pub async fn tend_tree(&mut self, spot: Position, sapling: &str) -> Result<(), TurtleError> {
+        let stand = spot.step(Direction::South);
+        self.move_point(stand).await?;
+        self.turn_towards(Direction::North).await?;
+
+        match self.inspect(MineDirection::Forward).await? {
+            Some(block) if trees::is_log(&block) => {
+                self.chop_trunk().await?;
+                self.move_point(stand).await?;
+                self.turn_towards(Direction::North).await?;
+                self.plant(sapling, MineDirection::Forward).await?;
+            }
+            Some(block) if trees::is_sapling(&block) => {}
+            Some(block) => tracing::warn!("{} is in the way at tree spot {:?}", block.name, spot),
+            None => self.plant(sapling, MineDirection::Forward).await?,
+        }
+
+        self.collect_drops().await
+    }
+
+    /// Cuts the trunk in front: steps into it, digs up for as long as there
+    /// is log above and comes back down.
+    async fn chop_trunk(&mut self) -> Result<(), TurtleError> {
+        self.move_and_mine_block(MoveDirection::Forward).await?;
+        let base = self.turtle.lock().await.pos;
+        while self
+            .inspect(MineDirection::Up)
+            .await?
+            .is_some_and(|b| trees::is_log(&b))
+        {
+            self.move_and_mine_block(MoveDirection::Up).await?;
+        }
+
+        self.move_point(base).await
+    }
+
+    /// Places a `seed` or sapling in `dir`, if the inventory has one.
+    async fn plant(&mut self, seed: &str, dir: MineDirection) -> Result<(), TurtleError> {
+        let Some(slot) = self.slot_with(seed).await else {
+            tracing::warn!("out of {}", seed);
+            return Ok(());
+        };
+
+        self.select(slot).await?;
+        let outcome = self.place(dir).await?;
+        if !outcome.ok {
+            tracing::warn!("could not plant {}: {:?}", seed, outcome.error);
+        }
+        Ok(())
+    }
+
+    /// Harvests the crop at `pos` from the cell above once it is ripe and
+    /// plants it again. An empty cell gets `seed`. Crop age comes from the
+    /// block state the turtle reports for the block below it.
+    pub async fn tend_crop(&mut self, pos: Position, seed: &str) -> Result<(), TurtleError> {
+        self.move_point(pos.offset(0, 1, 0)).await?;
+
+        let below = self
+            .turtle
+            .lock()
+            .await
+            .blocks
+            .iter()
+            .find(|b| b.direction == MineDirection::Down)
+            .cloned();
+        let Some(below) = below else {
+            tracing::warn!("no block reported below {:?}", pos);
+            return Ok(());
+        };
+
+        if !below.exists {
+            return self.plant(seed, MineDirection::Down).await;
+        }
+
+        match below.block.as_deref().and_then(farm::crop) {
+            Some(crop) if farm::is_ripe(crop, &below) => {
+                self.dig(MineDirection::Down).await?;
+                self.plant(crop.seed, MineDirection::Down).await?;
+            }
+            Some(_) => {}
+            None => tracing::warn!("{:?} is growing at crop cell {:?}", below.block, pos),
+        }
+
+        Ok(())
+    }
+
+    /// Picks up items lying in front of and below the turtle.
+    async fn collect_drops(&mut self) -> Result<(), TurtleError> {
+        for dir in [MineDirection::Forward, MineDirection::Down] {
+            for _ in 0..MAX_PICKUPS {
+                if !self.suck_items(dir, None).await?.ok {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The first slot holding `item`.
+    async fn slot_with(&self, item: &str) -> Option<i64> {
+        self.turtle
+            .lock()
+            .await
+            .slots
+            .iter()
+            .find(|s| s.type_field.as_ref().is_some_and(|t| t.name == item))
+            .map(|s| s.id)
+    }
+
+    /// Sucks stacks from the chest at `chest` until `wanted` more of `item`
+    /// are in the inventory or the chest has nothing left to give. Other
+    /// items that came along are put back.
+    async fn collect(
+        &mut self,
+        chest: Position,
+        item: &str,
+        wanted: i64,
+    ) -> Result<(), TurtleError> {
+        self.move_point(chest.offset(0, 1, 0)).await?;
+
+        let before = self.turtle.lock().await.slots.clone();
+        let start = self.held(item).await;
+        // one suck takes from one stack, so there is no point in trying more
+        // often than the chest has stacks
+        let stacks = self
+            .chests
+            .lock()
+            .await
+            .get(&chest)
+            .map_or(0, |c| c.chest.slots.len());
+        for _ in 0..stacks {
+            let got = self.held(item).await - start;
+            if got >= wanted {
+                break;
+            }
+            if !self
+                .suck_items(MineDirection::Down, Some(wanted - got))
+                .await?
+                .ok
+            {
+                break;
+            }
+        }
+
+        let after = self.turtle.lock().await.slots.clone();
+        let reserved = inventory::reserved(&self.config);
+        for (slot, count) in inventory::gained_besides(&before, &after, &reserved, item) {
+            self.select(slot).await?;
+            self.drop_items(MineDirection::Down, Some(count)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// How many of `item` the unreserved slots hold.
+    async fn held(&self, item: &str) -> i64 {
+        let reserved = inventory::reserved(&self.config);
+        inventory::count(&self.turtle.lock().await.slots, &reserved, item)
+    }
+
+    /// Makes room in the inventory: drops junk items and merges partial
+    /// stacks. Reserved slots are left alone. The next reply shows what
+    /// worked, so results are not needed and older clients manage too.
+    pub async fn manage_inventory(&mut self) -> Result<(), TurtleError> {
+        let slots = self.turtle.lock().await.slots.clone();
+        let junk = inventory::junk_slots(&slots, &self.config);
+        match self.junk_direction().await {
+            Some(direction) => {
+                for slot in junk {
+                    self.select(slot).await?;
+                    self.send_command(Action::new(ActionType::Drop {
+                        direction,
+                        count: None,
+                    }))
+                    .await?;
+                }
+            }
+            None if !junk.is_empty() => {
+                tracing::warn!("surrounded by containers, keeping the junk for now");
+            }
+            None => {}
+        }
+
+        let slots = self.turtle.lock().await.slots.clone();
+        for (from, to) in inventory::merges(&slots, &inventory::reserved(&self.config)) {
+            self.select(from).await?;
+            self.send_command(Action::new(ActionType::TransferTo {
+                slot: to,
+                count: None,
+            }))
             .await?;
+        }
 
         Ok(())
     }
 
+    /// A side to drop junk to that is not a chest or other container, so
+    /// junk lands on the ground and not in storage.
+    async fn junk_direction(&self) -> Option<MineDirection> {
+        let turtle = self.turtle.lock().await;
+        let chests = self.chests.lock().await;
+        [
+            MineDirection::Down,
+            MineDirection::Forward,
+            MineDirection::Up,
+        ]
+        .into_iter()
+        .find(|dir| {
+            let seen = turtle
+                .blocks
+                .iter()
+                .find(|b| b.direction == *dir)
+                .and_then(|b| b.block.as_deref());
+            // only the side in front depends on the heading
+            let indexed = (turtle.heading_known || *dir != MineDirection::Forward)
+                && chests
+                    .get(&turtle.pos.looking(turtle.direction, *dir))
+                    .is_some();
+            !indexed && !seen.is_some_and(inventory::is_container)
+        })
+    }
+
+    /// Burns inventory items, highest fuel value first, until the fuel level
+    /// reaches `target`. Only as many items as it takes are burnt and
+    /// reserved slots are left alone. Returns whether it got there.
+    pub async fn refuel_from_inventory(&mut self, target: i64) -> Result<bool, TurtleError> {
+        // reserved fuel is the user's to spend
+        let mut burnt = inventory::reserved(&self.config);
+        loop {
+            let turtle = self.turtle.lock().await;
+            if turtle.fuel >= target {
+                return Ok(true);
+            }
+
+            let missing = target - turtle.fuel;
+            let fuel = turtle
+                .slots
+                .iter()
+                .filter(|s| !burnt.contains(&s.id))
+                .filter_map(|s| {
+                    let item = s.type_field.as_ref()?;
+                    let value = self.config.fuel_values.get(&item.name)?;
+                    Some((s.id, *value, item.count))
+                })
+                .max_by_key(|(_, value, _)| *value);
+            drop(turtle);
+
+            let Some((slot, value, count)) = fuel else {
+                return Ok(false);
+            };
+
+            let needed = (missing + value - 1) / value;
+            self.select(slot).await?;
+            self.send_command(Action::new(ActionType::Refuel {
+                count: Some(needed.min(count)),
+            }))
+            .await?;
+            burnt.insert(slot);
+        }
+    }
+
     async fn parse_resp(&self, resp: InfoResp) {
         let mut turtle = self.turtle.lock().await;
+        let facing = turtle.heading_known.then_some(turtle.direction);
+        self.world
+            .lock()
+            .await
+            .observe(resp.pos, facing, &resp.blocks);
+        self.reservations.lock().await.occupy(turtle.id, resp.pos);
+        self.chests
+            .lock()
+            .await
+            .observe(resp.pos, resp.chest.as_ref());
+
         turtle.fuel = resp.fuel;
         turtle.blocks = resp.blocks;
         turtle.slots = resp.slots;
         turtle.pos = resp.pos;
+        turtle.last_seen = unix_time();
     }
 
     pub async fn send_command(&mut self, command: Action) -> Result<InfoResp, TurtleError> {
-        let packet = serde_json::to_string(&command).unwrap();
-        let turtle = self.turtle.lock().await;
-        drop(turtle);
-
-        if self.client_sender.send(Message::Text(packet)).is_err() {
-            println!("Error sending message to client. Maybe the WebSocket was closed?");
-            return Err(TurtleError::ErrorWebsockets);
-        }
-
-        let resp = self.response_receiver.recv().await.unwrap();
+        let timeout = self.command_timeout;
+        self.send_command_timeout(command, timeout).await
+    }
 
-        let resp: InfoResp = serde_json::from_str(&resp).unwrap();
+    /// Sends `command` and waits up to `timeout` for the reply carrying its
+    /// request id.
+    pub async fn send_command_timeout(
+        &mut self,
+        mut command: Action,
+        timeout: Duration,
+    ) -> Result<InfoResp, TurtleError> {
+        self.next_request_id += 1;
+        command.id = self.next_request_id;
+        command.action = protocol::encode(command.action, self.protocol);
+
+        let packet = serde_json::to_string(&command)
+            .map_err(|e| TurtleError::ErrorParsing(e.to_string()))?;
+
+        self.transport.send(packet)?;
+
+        let resp = match tokio::time::timeout(timeout, self.recv_response(command.id)).await {
+            Ok(resp) => resp?,
+            Err(_) => {
+                tracing::warn!("command {} timed out: {:?}", command.id, command.action);
+                return Err(TurtleError::ErrorTimeout(command.id));
+            }
+        };
 
         self.parse_resp(resp.clone()).await;
 
-        return Ok(resp);
+        Ok(resp)
     }
 
-    pub async fn mine_rect(&mut self, a: Position, b: Position) -> Result<(), TurtleError> {
-        let turtle = self.turtle.lock().await;
-        let turtle = turtle.clone();
-        let mut actions = VecDeque::new();
-        let mut initial_dir = turtle.direction.clone();
+    async fn recv_response(&mut self, id: u64) -> Result<InfoResp, TurtleError> {
+        loop {
+            let resp = self
+                .transport
+                .recv()
+                .await
+                .ok_or(TurtleError::ErrorWebsockets)?;
+
+            let resp: InfoResp = serde_json::from_str(&resp)
+                .map_err(|e| TurtleError::ErrorParsing(e.to_string()))?;
+
+            if !protocol::echoes_ids(self.protocol) {
+                return Ok(resp);
+            }
 
-        for y in a.y..=b.y {
-            let layer_start = Position { x: a.x, y, z: a.z };
-            let layer_end = Position { x: b.x, y, z: b.z };
+            // replies to commands that already timed out are stale, skip them
+            if resp.id < id {
+                tracing::warn!("dropping late response {} (waiting for {})", resp.id, id);
+                continue;
+            }
 
-            actions.push_back(QueuedAction::MovePoint(layer_start));
-            let mut layer_path = self.mine_layer(layer_start, layer_end).await?;
-            actions.append(&mut layer_path);
+            if resp.id != id {
+                return Err(TurtleError::ErrorMismatch {
+                    expected: id,
+                    got: resp.id,
+                });
+            }
 
-            // Change initial direction for the next layer to make it a continuous snake
-            initial_dir = if initial_dir == Direction::East {
-                Direction::West
+            return Ok(resp);
+        }
+    }
+
+    /// Moves into the quarry cell `pos` and digs the blocks above and below
+    /// it as asked. Cells that cannot be reached are skipped and counted as
+    /// such in the quarry's progress.
+    pub async fn dig_column(
+        &mut self,
+        pos: Position,
+        up: bool,
+        down: bool,
+    ) -> Result<(), TurtleError> {
+        let reached = match self.move_point(pos).await {
+            Ok(()) => {
+                if up {
+                    self.dig(MineDirection::Up).await?;
+                }
+                if down {
+                    self.dig(MineDirection::Down).await?;
+                }
+                true
+            }
+            Err(TurtleError::ErrorBlock | TurtleError::ErrorNoPath) => {
+                tracing::warn!("skipping unreachable quarry cell {:?}", pos);
+                false
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(progress) = &mut self.turtle.lock().await.progress {
+            let blocks = 1 + up as u64 + down as u64;
+            if reached {
+                progress.done += blocks;
             } else {
-                Direction::East
-            };
+                progress.skipped += blocks;
+            }
         }
 
-        let mut t = self.turtle.lock().await;
-        t.action_queue = actions.clone();
-        tracing::info!("actions {:?}", actions);
+        Ok(())
+    }
+
+    /// Digs and moves one block. While strip mining, any ore vein next to
+    /// the new position is mined out before returning.
+    pub async fn move_and_mine_block(&mut self, dir: MoveDirection) -> Result<(), TurtleError> {
+        self.dig_and_move(dir).await?;
+
+        let turtle = self.turtle.lock().await;
+        let follow_vein = matches!(turtle.curr_goal, Goal::Mine(_)) && self.ore_in_view(&turtle);
+        drop(turtle);
+
+        if follow_vein {
+            self.mine_vein().await?;
+        }
 
         Ok(())
     }
 
-    async fn mine_layer(
-        &self,
-        start: Position,
-        end: Position,
-    ) -> Result<VecDeque<QueuedAction>, TurtleError> {
+    /// Mines the block in `dir` unless it is another turtle. Returns whether
+    /// anything was mined.
+    pub async fn dig(&mut self, dir: MineDirection) -> Result<bool, TurtleError> {
         let turtle = self.turtle.lock().await;
-        let turtle = turtle.clone();
-
-        let mut actions = VecDeque::new();
-        let mut pos = start;
-        let mut dir = turtle.direction.clone();
-
-        while pos.y == start.y {
-            if dir == Direction::East && pos.z == end.z {
-                dir = Direction::South
-            } else if dir == Direction::South && pos.x == end.x {
-                dir = Direction::West
-            } else if dir == Direction::West && pos.z == start.z {
-                dir = Direction::South
-            } else if dir == Direction::South && pos.x == start.x {
-                dir = Direction::East
-            };
+        let other_turtle = turtle
+            .blocks
+            .iter()
+            .any(|b| b.direction == dir && b.block.as_deref().is_some_and(pathfinding::is_turtle));
+        drop(turtle);
+
+        if other_turtle {
+            tracing::warn!("not mining {:?}, another turtle is there", dir);
+            return Ok(false);
+        }
 
-            actions.push_back(QueuedAction::MoveDirection(dir));
-            move_in_direction(&mut pos, &dir);
+        self.send_command(Action::new(ActionType::Mine(dir)))
+            .await?;
+        Ok(true)
+    }
 
-            if pos.x > end.x {
-                break;
+    /// Reserves `pos` before moving into it, waiting while another turtle
+    /// holds it. Fails with `ErrorBlock` if the wait takes too long or this
+    /// turtle has to yield to break a deadlock, so the caller plans around.
+    async fn claim(&mut self, pos: Position) -> Result<(), TurtleError> {
+        let id = self.turtle.lock().await.id;
+        for _ in 0..MAX_CLAIM_WAITS {
+            match self.reservations.lock().await.claim(id, pos) {
+                Claim::Granted => return Ok(()),
+                Claim::Wait(_) => {}
+                Claim::Yield(holder) => {
+                    tracing::info!("turtle {} yields {:?} to turtle {}", id, pos, holder);
+                    return Err(TurtleError::ErrorBlock);
+                }
             }
+            tokio::time::sleep(CLAIM_RETRY).await;
         }
 
-        Ok(actions)
+        self.reservations.lock().await.unclaim(id);
+        tracing::warn!("turtle {} gave up waiting for {:?}", id, pos);
+        Err(TurtleError::ErrorBlock)
     }
 
-    pub async fn move_and_mine_block(&mut self, dir: MoveDirection) -> Result<(), TurtleError> {
+    /// Claims the cell in `dir` and moves into it. Until the heading is known
+    /// the cell ahead cannot be told, so forward moves go unclaimed.
+    async fn dig_and_move(&mut self, dir: MoveDirection) -> Result<(), TurtleError> {
         tracing::info!("attempting to move");
-        let turtle = self.turtle.lock().await;
-        let tblocks = turtle.blocks.clone();
         if dir == MoveDirection::Backward {
             return Err(TurtleError::ErrorBlock);
         }
 
-        let init_pos = turtle.pos.clone();
+        let turtle = self.turtle.lock().await;
+        let (id, init_pos) = (turtle.id, turtle.pos);
+        let target = (turtle.heading_known || dir != MoveDirection::Forward)
+            .then(|| turtle.pos.looking(turtle.direction, dir.swap()));
         drop(turtle);
-        loop {
+
+        if let Some(target) = target {
+            self.claim(target).await?;
+        }
+
+        let result = self.push_through(dir, init_pos).await;
+        if result.is_err() {
+            self.reservations.lock().await.unclaim(id);
+        }
+
+        result
+    }
+
+    async fn push_through(
+        &mut self,
+        dir: MoveDirection,
+        init_pos: Position,
+    ) -> Result<(), TurtleError> {
+        for _ in 0..MAX_MOVE_ATTEMPTS {
             let turtle = self.turtle.lock().await;
             if turtle.pos != init_pos {
-                break;
+                tracing::info!("moved");
+                return Ok(());
             }
+            let block = turtle
+                .blocks
+                .iter()
+                .find(|b| b.direction == dir.swap())
+                .cloned();
             drop(turtle);
 
-            if let Some(b) = tblocks.iter().filter(|b| b.direction == dir.swap()).next() {
-                if b.exists {
-                    self.send_command(Action {
-                        action: ActionType::Mine(dir.swap()),
-                    })
-                    .await?;
-                }
-
-                self.move_turtle(dir).await?;
+            if block.is_some_and(|b| b.exists) && !self.dig(dir.swap()).await? {
+                // wait for the turtle in the way to move on
+                tokio::time::sleep(CLAIM_RETRY).await;
             }
+
+            self.move_turtle(dir).await?;
         }
 
-        tracing::info!("moved");
+        if self.turtle.lock().await.pos != init_pos {
+            return Ok(());
+        }
 
-        Ok(())
+        tracing::warn!("could not move {:?} from {:?}", dir, init_pos);
+        Err(TurtleError::ErrorBlock)
     }
 
     pub async fn move_turtle(&mut self, dir: MoveDirection) -> Result<(), TurtleError> {
-        self.send_command(Action {
-            action: ActionType::Move(dir),
-        })
-        .await?;
+        self.send_command(Action::new(ActionType::Move(dir)))
+            .await?;
 
         Ok(())
     }
 
+    /// Moves to `target` along a path planned over the world map, planning
+    /// again whenever a step turns out to be blocked.
     pub async fn move_point(&mut self, target: Position) -> Result<(), TurtleError> {
-        tracing::info!("i am processing");
-        let turtle = self.turtle.lock().await;
-        let mut dx = target.x - turtle.pos.x;
-        let mut dy = target.y - turtle.pos.y;
-        let mut dz = target.z - turtle.pos.z;
-
-        drop(turtle);
+        let mut replans = 0;
+        loop {
+            let turtle = self.turtle.lock().await;
+            let (id, pos, facing) = (turtle.id, turtle.pos, turtle.direction);
+            drop(turtle);
 
-        // Check if the turtle is only one block away from its target
-        if dx.abs() + dy.abs() + dz.abs() == 1 {
-            if dx == 1 {
-                self.turn_towards(Direction::East).await?;
-            } else if dx == -1 {
-                self.turn_towards(Direction::West).await?;
-            } else if dz == 1 {
-                self.turn_towards(Direction::South).await?;
-            } else if dz == -1 {
-                self.turn_towards(Direction::North).await?;
+            if pos == target {
+                tracing::info!("completed");
+                return Ok(());
             }
 
-            if dx.abs() == 1 || dz.abs() == 1 {
-                self.move_and_mine_block(MoveDirection::Forward).await?;
-            } else if dy == 1 {
-                self.move_and_mine_block(MoveDirection::Up).await?;
-            } else if dy == -1 {
-                self.move_and_mine_block(MoveDirection::Down).await?;
+            let mut blocked = self.reservations.lock().await.blocked_for(id);
+            blocked.extend(&self.built);
+            // a protected target, such as a tree spot the turtle climbs back
+            // down into after chopping, is still reachable
+            blocked.extend(self.protected().await.into_iter().filter(|p| *p != target));
+            // search a copy so other turtles can report what they see
+            let nearby = self
+                .world
+                .lock()
+                .await
+                .around(pos, target, pathfinding::SEARCH_MARGIN);
+            let path = pathfinding::find_path(&nearby, pos, facing, target, &blocked);
+            let Some(path) = path else {
+                tracing::warn!("no path from {:?} to {:?}", pos, target);
+                return Err(TurtleError::ErrorNoPath);
+            };
+
+            match self.follow_path(&path).await {
+                Ok(()) => {}
+                Err(TurtleError::ErrorBlock) if replans < MAX_REPLANS => {
+                    replans += 1;
+                    tracing::info!("path to {:?} blocked, replanning", target);
+                }
+                Err(e) => return Err(e),
             }
+        }
+    }
 
-            return Ok(());
+    /// Cells paths never dig through: the chests the index knows, the
+    /// configured chests, and the sapling spots and crops of the farms.
+    async fn protected(&self) -> HashSet<Position> {
+        let mut cells: HashSet<Position> = self
+            .chests
+            .lock()
+            .await
+            .list()
+            .into_iter()
+            .map(|c| c.pos)
+            .collect();
+        cells.extend(self.config.deposit_chest);
+        cells.extend(self.config.fuel_chest);
+        if let Some(farm) = &self.config.tree_farm {
+            cells.insert(farm.chest);
+            cells.extend(trees::spots(farm));
+        }
+        if let Some(farm) = &self.config.crop_farm {
+            cells.insert(farm.chest);
+            cells.extend(farm::cells(farm));
         }
+        cells
+    }
 
-        while dx != 0 || dy != 0 || dz != 0 {
-            // Find out which axis has the longest distance remaining
-            let tmp = [(dx.abs(), 'x'), (dy.abs(), 'y'), (dz.abs(), 'z')];
+    async fn follow_path(&mut self, path: &[Position]) -> Result<(), TurtleError> {
+        for next in path {
+            self.step_to(*next).await?;
+        }
 
-            let (max_val, axis) = tmp.iter().max().unwrap();
+        Ok(())
+    }
 
-            if *max_val == 0 {
-                break;
-            }
+    /// Moves into the neighbouring cell `next`, digging if needed. Fails with
+    /// `ErrorBlock` if the map now says the cell must not be entered.
+    pub async fn step_to(&mut self, next: Position) -> Result<(), TurtleError> {
+        let dir = self.prepare_step(next).await?;
+        self.move_and_mine_block(dir).await
+    }
 
-            tracing::info!("thinking...");
+    /// Turns towards the neighbouring cell `next` and returns the move that
+    /// enters it.
+    async fn prepare_step(&mut self, next: Position) -> Result<MoveDirection, TurtleError> {
+        let pos = self.turtle.lock().await.pos;
+        let dir = match (next.x - pos.x, next.y - pos.y, next.z - pos.z) {
+            (0, 1, 0) => MoveDirection::Up,
+            (0, -1, 0) => MoveDirection::Down,
+            (1, 0, 0) => self.face(Direction::East).await?,
+            (-1, 0, 0) => self.face(Direction::West).await?,
+            (0, 0, 1) => self.face(Direction::South).await?,
+            (0, 0, -1) => self.face(Direction::North).await?,
+            _ => return Err(TurtleError::ErrorBlock),
+        };
 
-            match axis {
-                'x' => {
-                    self.turn_towards(if dx > 0 {
-                        Direction::East
-                    } else {
-                        Direction::West
-                    })
-                    .await?;
-                    for _ in 0..dx.abs() {
-                        self.move_and_mine_block(MoveDirection::Forward).await?;
-                    }
-                    dx = 0;
-                }
-                'y' => {
-                    for _ in 0..dy.abs() {
-                        if dy > 0 {
-                            self.move_and_mine_block(MoveDirection::Up).await?;
-                        } else {
-                            self.move_and_mine_block(MoveDirection::Down).await?;
-                        }
-                    }
-                    dy = 0;
-                }
-                'z' => {
-                    self.turn_towards(if dz > 0 {
-                        Direction::South
-                    } else {
-                        Direction::North
-                    })
-                    .await?;
-                    for _ in 0..dz.abs() {
-                        self.move_and_mine_block(MoveDirection::Forward).await?;
-                    }
-                    dz = 0;
-                }
-                _ => {}
-            }
+        if pathfinding::enter_cost(&*self.world.lock().await, &next).is_none() {
+            return Err(TurtleError::ErrorBlock);
         }
 
-        tracing::info!("completed");
+        Ok(dir)
+    }
 
-        Ok(())
+    async fn face(&mut self, dir: Direction) -> Result<MoveDirection, TurtleError> {
+        self.turn_towards(dir).await?;
+        Ok(MoveDirection::Forward)
     }
 }
 
-fn move_in_direction(pos: &mut Position, dir: &Direction) {
-    match dir {
-        Direction::North => pos.x -= 1,
-        Direction::South => pos.x += 1,
-        Direction::East => pos.z += 1,
-        Direction::West => pos.z -= 1,
-    }
+/// The live websocket connection currently driving a turtle.
+pub struct Session {
+    pub client_sender: UnboundedSender<Message>,
+    pub event_loop: JoinHandle<Result<(), TurtleError>>,
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 pub struct TurtleManager {
     pub turtles: Arc<Mutex<HashMap<usize, Arc<Mutex<Turtle>>>>>,
+    pub sessions: Arc<Mutex<HashMap<usize, Session>>>,
+    pub world: Arc<Mutex<WorldMap>>,
+    pub reservations: Arc<Mutex<Reservations>>,
+    pub chests: Arc<Mutex<ChestIndex>>,
+    pub jobs: Arc<Mutex<JobBoard>>,
+    pub config: Arc<Config>,
+    pub storage: Option<Storage>,
 }
 
 impl TurtleManager {
-    pub fn new() -> Self {
+    pub fn new(config: Arc<Config>) -> Self {
         Self {
             turtles: Arc::new(Mutex::new(HashMap::new())),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            world: Arc::new(Mutex::new(WorldMap::new())),
+            reservations: Arc::new(Mutex::new(Reservations::new())),
+            chests: Arc::new(Mutex::new(ChestIndex::new())),
+            jobs: Arc::new(Mutex::new(JobBoard::new())),
+            config,
+            storage: None,
+        }
+    }
+
+    /// Creates a manager backed by `storage`, restoring every saved turtle.
+    pub async fn load(storage: Storage, config: Arc<Config>) -> Result<Self, sqlx::Error> {
+        let turtles = storage.load_turtles().await?;
+        tracing::info!("loaded {} turtles from storage", turtles.len());
+
+        let cells = storage.load_cells().await?;
+        tracing::info!("loaded {} world cells from storage", cells.len());
+
+        let jobs = storage.load_jobs().await?;
+        tracing::info!("loaded {} jobs from storage", jobs.len());
+
+        let chests = storage.load_chests().await?;
+        tracing::info!("loaded {} chests from storage", chests.len());
+
+        let mut manager = Self::new(config);
+        for turtle in turtles {
+            manager.add_turtle(Arc::new(Mutex::new(turtle))).await;
+        }
+        let mut world = manager.world.lock().await;
+        for (pos, cell) in cells {
+            world.load(pos, cell);
+        }
+        drop(world);
+        let mut board = manager.jobs.lock().await;
+        for job in jobs {
+            board.load(job);
+        }
+        drop(board);
+        let mut index = manager.chests.lock().await;
+        for contents in chests {
+            index.load(contents);
+        }
+        drop(index);
+        manager.storage = Some(storage);
+
+        Ok(manager)
+    }
+
+    /// Writes every known turtle to storage, if the manager has any.
+    pub async fn save_all(&self) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+
+        let turtles: Vec<_> = self.turtles.lock().await.values().cloned().collect();
+        for turtle in turtles {
+            let turtle = turtle.lock().await.clone();
+            if let Err(e) = storage.save_turtle(&turtle).await {
+                tracing::error!("failed to save turtle {}: {}", turtle.id, e);
+            }
+        }
+
+        let jobs = self.jobs.lock().await.take_dirty();
+        for job in jobs {
+            if let Err(e) = storage.save_job(&job).await {
+                tracing::error!("failed to save job {}: {}", job.id, e);
+            }
+        }
+
+        let cells = self.world.lock().await.take_dirty();
+        if let Err(e) = storage.save_cells(&cells).await {
+            tracing::error!("failed to save {} world cells: {}", cells.len(), e);
+        }
+
+        let chests = self.chests.lock().await.take_dirty();
+        if let Err(e) = storage.save_chests(&chests).await {
+            tracing::error!("failed to save {} chests: {}", chests.len(), e);
+        }
+    }
+
+    pub async fn save_turtle(&self, id: usize) {
+        let (Some(storage), Some(turtle)) = (&self.storage, self.get_turtle(id).await) else {
+            return;
+        };
+
+        let turtle = turtle.lock().await.clone();
+        if let Err(e) = storage.save_turtle(&turtle).await {
+            tracing::error!("failed to save turtle {}: {}", turtle.id, e);
+        }
+    }
+
+    /// Registers the session for a turtle. If the turtle already has a live
+    /// session it is taken over: the old socket is closed and its event loop
+    /// stopped, so only one connection ever drives a turtle.
+    pub async fn start_session(&self, id: usize, session: Session) {
+        let old = self.sessions.lock().await.insert(id, session);
+        if let Some(old) = old {
+            if !old.event_loop.is_finished() {
+                tracing::warn!("turtle {} reconnected, taking over old session", id);
+            }
+            let _ = old.client_sender.send(Message::Close(None));
+            old.event_loop.abort();
+            // returns once the old loop is gone, so it never runs alongside
+            // the new one
+            let _ = old.event_loop.await;
+        }
+    }
+
+    /// Returns the state for the turtle in `handshake`, creating it on first
+    /// connect. A reconnecting turtle gets its existing state back, including
+    /// goals and queued actions, so it carries on where it stopped.
+    pub async fn connect(&self, handshake: Handshake) -> Arc<Mutex<Turtle>> {
+        let mut turtles = self.turtles.lock().await;
+        let turtle = match turtles.get(&handshake.id) {
+            Some(turtle) => {
+                let mut t = turtle.lock().await;
+                tracing::info!(
+                    "turtle {} resumed with {} queued actions",
+                    t.id,
+                    t.action_queue.len()
+                );
+                t.label = handshake.label;
+                t.kind = handshake.kind;
+                drop(t);
+                turtle.clone()
+            }
+            None => {
+                let turtle = Arc::new(Mutex::new(Turtle::new(handshake)));
+                let id = turtle.lock().await.id;
+                turtles.insert(id, turtle.clone());
+                turtle
+            }
+        };
+
+        turtle.lock().await.online = true;
+        turtle
+    }
+
+    /// Marks the turtle offline once the session that owns `client_sender`
+    /// ends. Sessions that were already taken over are left alone.
+    pub async fn end_session(&self, id: usize, client_sender: &UnboundedSender<Message>) {
+        let mut sessions = self.sessions.lock().await;
+        match sessions.get(&id) {
+            Some(session) if session.client_sender.same_channel(client_sender) => {
+                sessions.remove(&id);
+            }
+            _ => return,
+        }
+        drop(sessions);
+
+        self.reservations.lock().await.leave(id);
+        if let Some(turtle) = self.get_turtle(id).await {
+            turtle.lock().await.online = false;
+            tracing::info!("turtle {} is offline", id);
+        }
+        self.save_turtle(id).await;
+    }
+
+    /// Online turtles with nothing to do: no goal, no queued actions and no
+    /// job.
+    pub async fn idle_turtles(&self) -> Vec<usize> {
+        // no turtle stays locked while the job board is, so this and
+        // `schedule` cannot deadlock each other
+        let turtles: Vec<_> = self.turtles.lock().await.values().cloned().collect();
+        let mut candidates = Vec::new();
+        for turtle in turtles {
+            let t = turtle.lock().await;
+            if t.online && t.curr_goal == Goal::Idle && t.action_queue.is_empty() {
+                candidates.push(t.id);
+            }
+        }
+
+        let jobs = self.jobs.lock().await;
+        let mut idle: Vec<usize> = candidates
+            .into_iter()
+            .filter(|id| !jobs.is_busy(*id))
+            .collect();
+        idle.sort();
+
+        idle
+    }
+
+    /// Takes split jobs away from turtles that have been offline for longer
+    /// than `reassign_after`, then hands unassigned jobs to idle turtles.
+    pub async fn schedule(&self) {
+        let turtles: Vec<_> = self.turtles.lock().await.values().cloned().collect();
+        let now = unix_time();
+        let mut stale = Vec::new();
+        for turtle in turtles {
+            let t = turtle.lock().await;
+            if !t.online && now.saturating_sub(t.last_seen) > self.config.reassign_after {
+                stale.push(t.id);
+            }
+        }
+
+        let mut jobs = self.jobs.lock().await;
+        for id in stale {
+            jobs.release(id);
+        }
+        drop(jobs);
+
+        let idle = self.idle_turtles().await;
+        let mut jobs = self.jobs.lock().await;
+        for (job, turtle) in jobs.unassigned().into_iter().zip(idle) {
+            jobs.assign(job, turtle);
         }
     }
 
@@ -399,3 +1557,650 @@ impl TurtleManager {
         turtles.get(&id).cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::{controller, reply, reset_north_script};
+
+    const ORIGIN: Position = Position { x: 0, y: 0, z: 0 };
+
+    #[tokio::test]
+    async fn turn_towards_takes_the_short_way() {
+        let (mut tc, script) = controller(vec![
+            (ActionType::Turn(TurnDirection::Right), reply(ORIGIN, 100)),
+            (ActionType::Turn(TurnDirection::Left), reply(ORIGIN, 100)),
+            (ActionType::Turn(TurnDirection::Left), reply(ORIGIN, 100)),
+        ]);
+
+        tc.turn_towards(Direction::East).await.unwrap();
+        assert_eq!(tc.turtle.lock().await.direction, Direction::East);
+
+        tc.turn_towards(Direction::West).await.unwrap();
+        assert_eq!(tc.turtle.lock().await.direction, Direction::West);
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn reset_north_learns_the_heading() {
+        let (mut tc, script) = controller(reset_north_script(&reply(ORIGIN, 100)));
+        tc.turtle.lock().await.direction = Direction::South;
+
+        tc.reset_north().await.unwrap();
+
+        let turtle = tc.turtle.lock().await;
+        assert!(turtle.heading_known);
+        assert_eq!(turtle.direction, Direction::North);
+        assert_eq!(turtle.pos, ORIGIN.offset(0, 0, -1));
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn move_point_follows_the_path() {
+        let (mut tc, script) = controller(vec![
+            (
+                ActionType::Move(MoveDirection::Forward),
+                reply(ORIGIN.offset(0, 0, -1), 99),
+            ),
+            (
+                ActionType::Move(MoveDirection::Forward),
+                reply(ORIGIN.offset(0, 0, -2), 98),
+            ),
+        ]);
+        tc.turtle.lock().await.heading_known = true;
+
+        tc.move_point(ORIGIN.offset(0, 0, -2)).await.unwrap();
+
+        assert_eq!(tc.turtle.lock().await.pos, ORIGIN.offset(0, 0, -2));
+        assert!(tc
+            .reservations
+            .lock()
+            .await
+            .blocked_for(2)
+            .contains(&ORIGIN.offset(0, 0, -2)));
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn move_point_avoids_cells_held_by_other_turtles() {
+        let (mut tc, _) = controller(vec![]);
+        tc.turtle.lock().await.heading_known = true;
+        tc.reservations
+            .lock()
+            .await
+            .occupy(2, ORIGIN.offset(0, 0, -2));
+
+        let result = tc.move_point(ORIGIN.offset(0, 0, -2)).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorNoPath)));
+    }
+
+    #[tokio::test]
+    async fn only_veins_of_the_mined_material_are_followed() {
+        let (mut tc, script) = controller(vec![]);
+        {
+            let mut turtle = tc.turtle.lock().await;
+            turtle.main_goal = Goal::Mine(Material::Diamond);
+            turtle.curr_goal = Goal::Mine(Material::Diamond);
+        }
+        {
+            let mut world = tc.world.lock().await;
+            world.set(
+                ORIGIN.offset(0, 1, 0),
+                CellState::Block("minecraft:coal_ore".to_string()),
+            );
+            for (x, y, z) in [(0, -1, 0), (1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)] {
+                world.set(
+                    ORIGIN.offset(x, y, z),
+                    CellState::Block("minecraft:stone".to_string()),
+                );
+            }
+        }
+
+        assert_eq!(tc.mine_vein().await.unwrap(), 0);
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn reserved_fuel_is_not_burnt() {
+        let (mut tc, script) = controller(vec![]);
+        tc.config = Arc::new(Config {
+            reserved_slots: HashMap::from([("fuel".to_string(), 16)]),
+            ..Config::default()
+        });
+        tc.turtle.lock().await.slots = vec![Slot {
+            id: 16,
+            type_field: Some(Type {
+                name: "minecraft:coal".to_string(),
+                count: 64,
+            }),
+            space: 0,
+        }];
+
+        assert!(!tc.refuel_from_inventory(1000).await.unwrap());
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn junk_is_not_dropped_into_the_chest_below() {
+        let cobblestone = Slot {
+            id: 1,
+            type_field: Some(Type {
+                name: "minecraft:cobblestone".to_string(),
+                count: 64,
+            }),
+            space: 0,
+        };
+        let (mut tc, script) = controller(vec![
+            (ActionType::Select(1), reply(ORIGIN, 100)),
+            (
+                ActionType::Drop {
+                    direction: MineDirection::Forward,
+                    count: None,
+                },
+                reply(ORIGIN, 100),
+            ),
+        ]);
+        {
+            let mut turtle = tc.turtle.lock().await;
+            turtle.slots = vec![cobblestone];
+            turtle.blocks = vec![Block {
+                direction: MineDirection::Down,
+                exists: true,
+                block: Some("minecraft:chest".to_string()),
+                state: HashMap::new(),
+            }];
+        }
+
+        tc.manage_inventory().await.unwrap();
+
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn move_point_never_digs_through_a_chest() {
+        let (mut tc, _) = controller(vec![]);
+        tc.turtle.lock().await.heading_known = true;
+        let chest = ORIGIN.offset(0, 0, -1);
+        {
+            let mut world = tc.world.lock().await;
+            world.set(chest, CellState::Block("minecraft:chest".to_string()));
+            for (x, y, z) in [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1)] {
+                world.set(
+                    ORIGIN.offset(x, y, z),
+                    CellState::Block("minecraft:bedrock".to_string()),
+                );
+            }
+        }
+        tc.chests.lock().await.load(ChestContents {
+            pos: chest,
+            chest: Chest::default(),
+            seen_at: 0,
+        });
+
+        let result = tc.move_point(ORIGIN.offset(0, 0, -2)).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorNoPath)));
+    }
+
+    #[tokio::test]
+    async fn fetch_delivers_what_is_in_stock() {
+        let iron = |count| Slot {
+            id: 1,
+            type_field: Some(Type {
+                name: "minecraft:iron_ingot".to_string(),
+                count,
+            }),
+            space: 64 - count,
+        };
+        let ok = Some(ActionResult::Outcome(Outcome {
+            ok: true,
+            error: None,
+        }));
+        let destination = ORIGIN.offset(0, -1, -1);
+        let (mut tc, script) = controller(vec![
+            (
+                ActionType::Suck {
+                    direction: MineDirection::Down,
+                    count: Some(64),
+                },
+                InfoResp {
+                    slots: vec![iron(10)],
+                    result: ok.clone(),
+                    ..reply(ORIGIN, 100)
+                },
+            ),
+            (
+                ActionType::Move(MoveDirection::Forward),
+                InfoResp {
+                    slots: vec![iron(10)],
+                    ..reply(ORIGIN.offset(0, 0, -1), 99)
+                },
+            ),
+            (
+                ActionType::Select(1),
+                InfoResp {
+                    slots: vec![iron(10)],
+                    ..reply(ORIGIN.offset(0, 0, -1), 99)
+                },
+            ),
+            (
+                ActionType::Drop {
+                    direction: MineDirection::Down,
+                    count: Some(10),
+                },
+                InfoResp {
+                    result: ok,
+                    ..reply(ORIGIN.offset(0, 0, -1), 99)
+                },
+            ),
+        ]);
+        tc.turtle.lock().await.heading_known = true;
+        tc.chests.lock().await.observe(
+            ORIGIN,
+            Some(&Chest {
+                slots: vec![Slot2 {
+                    name: "minecraft:iron_ingot".to_string(),
+                    count: 10,
+                    nbt: None,
+                }],
+                size: 27,
+            }),
+        );
+
+        let delivered = tc
+            .fetch("minecraft:iron_ingot", 64, destination)
+            .await
+            .unwrap();
+
+        assert_eq!(delivered, 10);
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn fetch_without_a_source_stays_put() {
+        let (mut tc, _) = controller(vec![]);
+
+        let delivered = tc
+            .fetch("minecraft:iron_ingot", 64, ORIGIN.offset(0, -1, -8))
+            .await
+            .unwrap();
+
+        assert_eq!(delivered, 0);
+        assert_eq!(tc.turtle.lock().await.pos, ORIGIN);
+    }
+
+    #[tokio::test]
+    async fn a_retried_fetch_counts_what_it_already_carries() {
+        let carried = InfoResp {
+            slots: vec![Slot {
+                id: 1,
+                type_field: Some(Type {
+                    name: "minecraft:iron_ingot".to_string(),
+                    count: 10,
+                }),
+                space: 54,
+            }],
+            ..reply(ORIGIN.offset(0, 0, -1), 99)
+        };
+        let (mut tc, script) = controller(vec![
+            (ActionType::Move(MoveDirection::Forward), carried.clone()),
+            (ActionType::Select(1), carried.clone()),
+            (
+                ActionType::Drop {
+                    direction: MineDirection::Down,
+                    count: Some(10),
+                },
+                InfoResp {
+                    result: Some(ActionResult::Outcome(Outcome {
+                        ok: true,
+                        error: None,
+                    })),
+                    ..reply(ORIGIN.offset(0, 0, -1), 99)
+                },
+            ),
+        ]);
+        {
+            let mut turtle = tc.turtle.lock().await;
+            turtle.heading_known = true;
+            turtle.slots = carried.slots.clone();
+        }
+
+        let delivered = tc
+            .fetch("minecraft:iron_ingot", 10, ORIGIN.offset(0, -1, -1))
+            .await
+            .unwrap();
+
+        assert_eq!(delivered, 10);
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn restock_names_what_storage_lacks() {
+        let (mut tc, _) = controller(vec![]);
+
+        let result = tc
+            .restock(&[("minecraft:oak_planks".to_string(), 20)])
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TurtleError::ErrorMissingItems(missing))
+                if missing == vec![("minecraft:oak_planks".to_string(), 20)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_growing_sapling_is_left_to_grow() {
+        let sapling = BlockState {
+            name: "minecraft:oak_sapling".to_string(),
+            state: HashMap::new(),
+            tags: vec!["minecraft:saplings".to_string()],
+        };
+        let nothing = |direction| {
+            (
+                ActionType::Suck {
+                    direction,
+                    count: None,
+                },
+                InfoResp {
+                    result: Some(ActionResult::Outcome(Outcome {
+                        ok: false,
+                        error: Some("No items to take".to_string()),
+                    })),
+                    ..reply(ORIGIN, 100)
+                },
+            )
+        };
+        let (mut tc, script) = controller(vec![
+            (
+                ActionType::Inspect(MineDirection::Forward),
+                InfoResp {
+                    result: Some(ActionResult::Block(Some(sapling))),
+                    ..reply(ORIGIN, 100)
+                },
+            ),
+            nothing(MineDirection::Forward),
+            nothing(MineDirection::Down),
+        ]);
+        tc.turtle.lock().await.heading_known = true;
+
+        tc.tend_tree(ORIGIN.offset(0, 0, -1), "minecraft:oak_sapling")
+            .await
+            .unwrap();
+
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn unreachable_quarry_cells_are_counted_as_skipped() {
+        let (mut tc, _) = controller(vec![]);
+        let cell = ORIGIN.offset(1, 0, 0);
+        tc.reservations.lock().await.occupy(2, cell);
+        tc.turtle.lock().await.progress = Some(Progress {
+            done: 0,
+            total: 3,
+            skipped: 0,
+        });
+
+        tc.dig_column(cell, true, true).await.unwrap();
+
+        assert_eq!(
+            tc.turtle.lock().await.progress,
+            Some(Progress {
+                done: 0,
+                total: 3,
+                skipped: 3,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn placing_clears_the_cell_first() {
+        let above = ORIGIN.offset(0, 1, 0);
+        let stone = InfoResp {
+            slots: vec![Slot {
+                id: 1,
+                type_field: Some(Type {
+                    name: "minecraft:stone".to_string(),
+                    count: 1,
+                }),
+                space: 63,
+            }],
+            ..reply(above, 100)
+        };
+        let (mut tc, script) = controller(vec![
+            (ActionType::Mine(MineDirection::Down), stone.clone()),
+            (ActionType::Select(1), stone.clone()),
+            (
+                ActionType::Place(MineDirection::Down),
+                InfoResp {
+                    result: Some(ActionResult::Outcome(Outcome {
+                        ok: true,
+                        error: None,
+                    })),
+                    ..stone.clone()
+                },
+            ),
+        ]);
+        {
+            let mut turtle = tc.turtle.lock().await;
+            turtle.pos = above;
+            turtle.slots = stone.slots;
+        }
+        tc.world
+            .lock()
+            .await
+            .set(ORIGIN, CellState::Block("minecraft:dirt".to_string()));
+
+        tc.place_block(ORIGIN, "minecraft:stone").await.unwrap();
+
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn logs_are_only_burnt_when_fuel_runs_low() {
+        let logs = |fuel| InfoResp {
+            slots: vec![Slot {
+                id: 1,
+                type_field: Some(Type {
+                    name: "minecraft:oak_log".to_string(),
+                    count: 64,
+                }),
+                space: 0,
+            }],
+            ..reply(ORIGIN, fuel)
+        };
+        let (mut tc, script) = controller(vec![
+            (ActionType::Select(1), logs(1990)),
+            (ActionType::Refuel { count: Some(1) }, logs(2005)),
+        ]);
+        {
+            let mut turtle = tc.turtle.lock().await;
+            turtle.slots = logs(1990).slots;
+            turtle.fuel = 500;
+        }
+
+        tc.run_queued_action(&QueuedAction::BurnFuel).await.unwrap();
+        assert_eq!(script.remaining(), 2);
+
+        tc.turtle.lock().await.fuel = 1990;
+        let config = Config {
+            refuel_threshold: 1995,
+            ..Config::default()
+        };
+        tc.config = Arc::new(config);
+        tc.run_queued_action(&QueuedAction::BurnFuel).await.unwrap();
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn ripe_wheat_is_harvested_and_replanted() {
+        let seeds = InfoResp {
+            slots: vec![Slot {
+                id: 1,
+                type_field: Some(Type {
+                    name: "minecraft:wheat_seeds".to_string(),
+                    count: 2,
+                }),
+                space: 62,
+            }],
+            ..reply(ORIGIN, 100)
+        };
+        let (mut tc, script) = controller(vec![
+            (ActionType::Mine(MineDirection::Down), seeds.clone()),
+            (ActionType::Select(1), seeds.clone()),
+            (
+                ActionType::Place(MineDirection::Down),
+                InfoResp {
+                    result: Some(ActionResult::Outcome(Outcome {
+                        ok: true,
+                        error: None,
+                    })),
+                    ..seeds
+                },
+            ),
+        ]);
+        tc.turtle.lock().await.heading_known = true;
+        tc.turtle.lock().await.blocks = vec![Block {
+            direction: MineDirection::Down,
+            exists: true,
+            block: Some("minecraft:wheat".to_string()),
+            state: HashMap::from([("age".to_string(), serde_json::json!(7))]),
+        }];
+
+        tc.tend_crop(ORIGIN.offset(0, -1, 0), "minecraft:wheat_seeds")
+            .await
+            .unwrap();
+
+        assert_eq!(script.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn inspect_returns_the_block_state() {
+        let wheat = BlockState {
+            name: "minecraft:wheat".to_string(),
+            state: HashMap::from([("age".to_string(), serde_json::json!(7))]),
+            tags: vec!["minecraft:crops".to_string()],
+        };
+        let (mut tc, _) = controller(vec![(
+            ActionType::Inspect(MineDirection::Down),
+            InfoResp {
+                result: Some(ActionResult::Block(Some(wheat.clone()))),
+                ..reply(ORIGIN, 100)
+            },
+        )]);
+
+        let block = tc.inspect(MineDirection::Down).await.unwrap();
+
+        assert_eq!(block, Some(wheat));
+    }
+
+    #[tokio::test]
+    async fn actions_without_a_result_are_an_error() {
+        let (mut tc, _) = controller(vec![(
+            ActionType::Place(MineDirection::Forward),
+            reply(ORIGIN, 100),
+        )]);
+
+        let result = tc.place(MineDirection::Forward).await;
+
+        assert!(matches!(result, Err(TurtleError::ErrorUnsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn dig_never_mines_a_turtle() {
+        let (mut tc, _) = controller(vec![]);
+        tc.turtle.lock().await.blocks = vec![Block {
+            direction: MineDirection::Forward,
+            exists: true,
+            block: Some("computercraft:turtle_normal".to_string()),
+            state: HashMap::new(),
+        }];
+
+        assert!(!tc.dig(MineDirection::Forward).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_new_session_stops_the_old_loop_first() {
+        let manager = TurtleManager::new(Arc::new(Config::default()));
+        let (client_sender, _) = tokio::sync::mpsc::unbounded_channel();
+        let running = Arc::new(());
+        let held = running.clone();
+        let event_loop = tokio::spawn(async move {
+            let _held = held;
+            std::future::pending::<Result<(), TurtleError>>().await
+        });
+        manager
+            .start_session(
+                1,
+                Session {
+                    client_sender: client_sender.clone(),
+                    event_loop,
+                },
+            )
+            .await;
+
+        manager
+            .start_session(
+                1,
+                Session {
+                    client_sender,
+                    event_loop: tokio::spawn(async { Ok(()) }),
+                },
+            )
+            .await;
+
+        assert_eq!(Arc::strong_count(&running), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn scheduling_and_idle_checks_run_side_by_side() {
+        let manager = Arc::new(TurtleManager::new(Arc::new(Config::default())));
+        for id in 1..=4 {
+            let mut turtle = Turtle::new(Handshake {
+                id,
+                label: None,
+                kind: TurtleKind::Normal,
+                version: PROTOCOL_VERSION,
+            });
+            turtle.online = id % 2 == 1;
+            turtle.last_seen = 0;
+            manager.add_turtle(Arc::new(Mutex::new(turtle))).await;
+        }
+        let jobs = manager.jobs.lock().await.create_split(
+            ORIGIN,
+            ORIGIN.offset(7, -5, 7),
+            QuarryOrder::TopDown,
+            2,
+        );
+        for (job, owner) in jobs.iter().zip([2, 4]) {
+            manager.jobs.lock().await.assign(job.id, owner);
+        }
+
+        let mut tasks = Vec::new();
+        for _ in 0..50 {
+            let m = manager.clone();
+            tasks.push(tokio::spawn(async move { m.schedule().await }));
+            let m = manager.clone();
+            tasks.push(tokio::spawn(async move {
+                m.idle_turtles().await;
+            }));
+        }
+        let all = futures::future::join_all(tasks);
+        tokio::time::timeout(Duration::from_secs(5), all)
+            .await
+            .expect("schedule and idle_turtles deadlocked");
+
+        let mut owners: Vec<_> = manager
+            .jobs
+            .lock()
+            .await
+            .list()
+            .iter()
+            .map(|j| j.owner)
+            .collect();
+        owners.sort();
+        assert_eq!(owners, vec![Some(1), Some(3)]);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use models::{Direction, Position};
+
+use crate::models::unix_time;
+use crate::world::{CellState, WorldMap};
+
+const MOVE_COST: u32 = 1;
+const TURN_COST: u32 = 1;
+/// Digging costs a mine command and may drop junk into the inventory, so a
+/// short detour through air is preferred over tunnelling.
+const DIG_COST: u32 = 4;
+/// Unknown cells are probably solid underground.
+const UNKNOWN_COST: u32 = 2;
+const MAX_EXPANSIONS: usize = 200_000;
+/// Turtles move, so a sighting only blocks a cell for a short while.
+const TURTLE_SIGHTING_SECS: u64 = 30;
+
+/// How far past the box spanned by start and goal a search uses the known
+/// map. Cells further out count as unknown.
+pub const SEARCH_MARGIN: i64 = 32;
+
+const MIN_Y: i64 = -64;
+const MAX_Y: i64 = 319;
+
+const UNBREAKABLE: &[&str] = &[
+    "minecraft:bedrock",
+    "minecraft:barrier",
+    "minecraft:command_block",
+    "minecraft:chain_command_block",
+    "minecraft:repeating_command_block",
+    "minecraft:structure_block",
+    "minecraft:end_portal",
+    "minecraft:end_portal_frame",
+    "minecraft:nether_portal",
+    "minecraft:reinforced_deepslate",
+];
+
+pub fn is_turtle(name: &str) -> bool {
+    name.starts_with("computercraft:turtle")
+}
+
+pub fn is_liquid(name: &str) -> bool {
+    matches!(
+        name,
+        "minecraft:water" | "minecraft:lava" | "minecraft:flowing_water" | "minecraft:flowing_lava"
+    )
+}
+
+pub fn is_unbreakable(name: &str) -> bool {
+    UNBREAKABLE.contains(&name)
+}
+
+/// Cost of entering `pos`, or `None` if the turtle must not go there.
+pub fn enter_cost(world: &WorldMap, pos: &Position) -> Option<u32> {
+    if pos.y < MIN_Y || pos.y > MAX_Y {
+        return None;
+    }
+
+    let Some(cell) = world.get(pos) else {
+        return Some(MOVE_COST + UNKNOWN_COST);
+    };
+
+    match &cell.state {
+        CellState::Air => Some(MOVE_COST),
+        CellState::Unknown => Some(MOVE_COST + UNKNOWN_COST),
+        CellState::Block(name) if is_turtle(name) => {
+            if unix_time().saturating_sub(cell.observed_at) < TURTLE_SIGHTING_SECS {
+                None
+            } else {
+                Some(MOVE_COST + UNKNOWN_COST)
+            }
+        }
+        CellState::Block(name) if is_liquid(name) || is_unbreakable(name) => None,
+        CellState::Block(_) => Some(MOVE_COST + DIG_COST),
+    }
+}
+
+fn turns(from: Direction, to: Direction) -> u32 {
+    let index = |d| match d {
+        Direction::North => 0,
+        Direction::East => 1,
+        Direction::South => 2,
+        Direction::West => 3,
+    };
+    match (index(to) + 4 - index(from)) % 4 {
+        0 => 0,
+        2 => 2,
+        _ => 1,
+    }
+}
+
+/// Blocks between `a` and `b` moving along the axes.
+pub fn distance(a: &Position, b: &Position) -> u32 {
+    ((a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs()) as u32
+}
+
+/// Plans a route from `start` to `goal` over the known world map with A*.
+/// Returns the cells to step through, excluding `start`. Cells in `blocked`
+/// are never entered.
+pub fn find_path(
+    world: &WorldMap,
+    start: Position,
+    facing: Direction,
+    goal: Position,
+    blocked: &HashSet<Position>,
+) -> Option<Vec<Position>> {
+    if start == goal {
+        return Some(vec![]);
+    }
+    if blocked.contains(&goal) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<(Position, Direction), u32> = HashMap::new();
+    let mut came_from: HashMap<(Position, Direction), (Position, Direction)> = HashMap::new();
+
+    best.insert((start, facing), 0);
+    open.push(Reverse((distance(&start, &goal), 0, start, facing)));
+
+    let mut expansions = 0;
+    while let Some(Reverse((_, cost, pos, facing))) = open.pop() {
+        if pos == goal {
+            let mut path = vec![pos];
+            let mut state = (pos, facing);
+            while let Some(prev) = came_from.get(&state) {
+                path.push(prev.0);
+                state = *prev;
+            }
+            // drop `start`
+            path.pop();
+            path.reverse();
+            return Some(path);
+        }
+
+        if best.get(&(pos, facing)).is_some_and(|b| *b < cost) {
+            continue;
+        }
+
+        expansions += 1;
+        if expansions > MAX_EXPANSIONS {
+            tracing::warn!("no path from {:?} to {:?} within search limit", start, goal);
+            return None;
+        }
+
+        let horizontal = [
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ]
+        .map(|d| (pos.step(d), d, turns(facing, d) * TURN_COST));
+        let vertical = [pos.offset(0, 1, 0), pos.offset(0, -1, 0)].map(|p| (p, facing, 0));
+
+        for (next, next_facing, turn_cost) in horizontal.into_iter().chain(vertical) {
+            if blocked.contains(&next) {
+                continue;
+            }
+            let Some(enter) = enter_cost(world, &next) else {
+                continue;
+            };
+
+            let next_cost = cost + turn_cost + enter;
+            let state = (next, next_facing);
+            if best.get(&state).is_some_and(|b| *b <= next_cost) {
+                continue;
+            }
+
+            best.insert(state, next_cost);
+            came_from.insert(state, (pos, facing));
+            open.push(Reverse((
+                next_cost + distance(&next, &goal) * MOVE_COST,
+                next_cost,
+                next,
+                next_facing,
+            )));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{Cell, AIR};
+
+    use super::*;
+
+    const START: Position = Position { x: 0, y: 0, z: 0 };
+
+    fn world(cells: &[((i64, i64, i64), &str)]) -> WorldMap {
+        let mut world = WorldMap::new();
+        for ((x, y, z), block) in cells {
+            world.set(
+                Position {
+                    x: *x,
+                    y: *y,
+                    z: *z,
+                },
+                CellState::from_block_name(Some(block.to_string())),
+            );
+        }
+        world
+    }
+
+    fn route(
+        world: &WorldMap,
+        goal: Position,
+        blocked: &HashSet<Position>,
+    ) -> Option<Vec<Position>> {
+        find_path(world, START, Direction::East, goal, blocked)
+    }
+
+    #[test]
+    fn goes_around_what_cannot_be_dug() {
+        for wall in ["minecraft:bedrock", "minecraft:water"] {
+            let world = world(&[
+                ((1, 0, 0), wall),
+                ((1, 1, 0), wall),
+                ((1, -1, 0), wall),
+                ((0, 0, 1), AIR),
+                ((1, 0, 1), AIR),
+                ((2, 0, 1), AIR),
+                ((2, 0, 0), AIR),
+            ]);
+
+            let path = route(&world, START.offset(2, 0, 0), &HashSet::new()).unwrap();
+
+            assert_eq!(
+                path,
+                vec![
+                    START.offset(0, 0, 1),
+                    START.offset(1, 0, 1),
+                    START.offset(2, 0, 1),
+                    START.offset(2, 0, 0),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn digs_through_when_a_detour_costs_more() {
+        let world = world(&[((1, 0, 0), "minecraft:stone"), ((2, 0, 0), AIR)]);
+
+        let path = route(&world, START.offset(2, 0, 0), &HashSet::new()).unwrap();
+
+        assert_eq!(path, vec![START.offset(1, 0, 0), START.offset(2, 0, 0)]);
+    }
+
+    #[test]
+    fn takes_a_short_detour_through_air_over_digging() {
+        let world = world(&[
+            ((1, 0, 0), "minecraft:stone"),
+            ((0, 1, 0), AIR),
+            ((1, 1, 0), AIR),
+            ((2, 1, 0), AIR),
+            ((2, 0, 0), AIR),
+        ]);
+
+        let path = route(&world, START.offset(2, 0, 0), &HashSet::new()).unwrap();
+
+        assert!(!path.contains(&START.offset(1, 0, 0)));
+        assert_eq!(path.len(), 4);
+    }
+
+    #[test]
+    fn recently_seen_turtles_block_their_cell() {
+        let ahead = START.offset(1, 0, 0);
+        let mut world = world(&[((2, 0, 0), AIR)]);
+        world.load(
+            ahead,
+            Cell {
+                state: CellState::Block("computercraft:turtle_normal".to_string()),
+                observed_at: unix_time(),
+            },
+        );
+        assert_eq!(enter_cost(&world, &ahead), None);
+        assert!(!route(&world, START.offset(2, 0, 0), &HashSet::new())
+            .unwrap()
+            .contains(&ahead));
+
+        world.load(
+            ahead,
+            Cell {
+                state: CellState::Block("computercraft:turtle_normal".to_string()),
+                observed_at: unix_time() - TURTLE_SIGHTING_SECS,
+            },
+        );
+        assert_eq!(enter_cost(&world, &ahead), Some(MOVE_COST + UNKNOWN_COST));
+    }
+
+    #[test]
+    fn blocked_cells_are_never_entered() {
+        let world = world(&[]);
+        let blocked = HashSet::from([START.offset(1, 0, 0)]);
+
+        let path = route(&world, START.offset(2, 0, 0), &blocked).unwrap();
+
+        assert!(!path.contains(&START.offset(1, 0, 0)));
+        assert_eq!(route(&world, START.offset(1, 0, 0), &blocked), None);
+    }
+
+    #[test]
+    fn gives_up_on_an_enclosed_goal() {
+        let goal = START.offset(5, 0, 0);
+        let walls: Vec<_> = [
+            (1, 0, 0),
+            (-1, 0, 0),
+            (0, 1, 0),
+            (0, -1, 0),
+            (0, 0, 1),
+            (0, 0, -1),
+        ]
+        .into_iter()
+        .map(|(x, y, z)| ((goal.x + x, goal.y + y, goal.z + z), "minecraft:bedrock"))
+        .collect();
+        let world = world(&walls);
+
+        assert_eq!(route(&world, goal, &HashSet::new()), None);
+    }
+
+    #[test]
+    fn stays_within_the_build_height() {
+        let world = world(&[]);
+
+        assert_eq!(enter_cost(&world, &START.offset(0, MIN_Y - 1, 0)), None);
+        assert_eq!(enter_cost(&world, &START.offset(0, MAX_Y + 1, 0)), None);
+        assert!(enter_cost(&world, &START.offset(0, MIN_Y, 0)).is_some());
+
+        let floor = START.offset(0, MIN_Y, 0);
+        let path = find_path(
+            &world,
+            floor,
+            Direction::East,
+            floor.offset(2, 0, 0),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert!(path.iter().all(|p| p.y >= MIN_Y));
+    }
+}
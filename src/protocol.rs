@@ -0,0 +1,139 @@
+use models::{ActionType, MineDirection, Rejected, Side, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+/// Whether the server can talk to a client speaking `version`.
+pub fn supported(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Whether replies from a client speaking `version` carry the id of the
+/// action they answer. Version 1 replies are taken in the order sent.
+pub fn echoes_ids(version: u32) -> bool {
+    version >= 2
+}
+
+pub fn rejection(version: u32) -> Rejected {
+    Rejected {
+        reason: format!(
+            "client protocol v{} is not supported, this server speaks v{} to v{}; \
+            reinstall the client with `wget run <server>/install.lua`",
+            version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        ),
+        min_version: MIN_PROTOCOL_VERSION,
+        max_version: PROTOCOL_VERSION,
+    }
+}
+
+/// A `Slot` action, which clients before version 3 run as the turtle API
+/// function `name` with the first letter lowered.
+fn slot_action(name: &str, dir: Option<MineDirection>, args: Vec<i64>) -> ActionType {
+    let suffix = match dir {
+        Some(MineDirection::Up) => "Up",
+        Some(MineDirection::Down) => "Down",
+        Some(MineDirection::Forward) | None => "",
+    };
+
+    ActionType::Slot {
+        name: format!("{}{}", name, suffix),
+        args,
+    }
+}
+
+/// Rewrites `action` into the form a client speaking `version` understands.
+pub fn encode(action: ActionType, version: u32) -> ActionType {
+    match action {
+        ActionType::Refuel { count } if version < 4 => {
+            slot_action("Refuel", None, count.into_iter().collect())
+        }
+        action if version >= 3 => action,
+        ActionType::Select(slot) if version < 2 => slot_action("Select", None, vec![slot]),
+        ActionType::Place(dir) => slot_action("Place", Some(dir), vec![]),
+        ActionType::Drop { direction, count } => {
+            slot_action("Drop", Some(direction), count.into_iter().collect())
+        }
+        ActionType::Suck { direction, count } => {
+            slot_action("Suck", Some(direction), count.into_iter().collect())
+        }
+        ActionType::Inspect(dir) => slot_action("Inspect", Some(dir), vec![]),
+        ActionType::Compare(dir) => slot_action("Compare", Some(dir), vec![]),
+        ActionType::Attack(dir) => slot_action("Attack", Some(dir), vec![]),
+        ActionType::TransferTo { slot, count } => slot_action(
+            "TransferTo",
+            None,
+            [slot].into_iter().chain(count).collect(),
+        ),
+        ActionType::GetItemDetail { slot, .. } => {
+            slot_action("GetItemDetail", None, slot.into_iter().collect())
+        }
+        ActionType::Equip(Side::Left) => slot_action("EquipLeft", None, vec![]),
+        ActionType::Equip(Side::Right) => slot_action("EquipRight", None, vec![]),
+        ActionType::Craft(limit) => slot_action("Craft", None, limit.into_iter().collect()),
+        action => action,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_is_spelled_as_a_slot_action_for_v1() {
+        assert_eq!(
+            encode(ActionType::Select(3), 1),
+            ActionType::Slot {
+                name: "Select".to_string(),
+                args: vec![3],
+            }
+        );
+        assert_eq!(encode(ActionType::Select(3), 2), ActionType::Select(3));
+    }
+
+    #[test]
+    fn new_actions_fall_back_to_slot_actions_before_v3() {
+        let drop = ActionType::Drop {
+            direction: MineDirection::Up,
+            count: Some(8),
+        };
+        assert_eq!(
+            encode(drop.clone(), 2),
+            ActionType::Slot {
+                name: "DropUp".to_string(),
+                args: vec![8],
+            }
+        );
+        assert_eq!(encode(drop.clone(), 3), drop);
+    }
+
+    #[test]
+    fn refuel_counts_fall_back_to_slot_actions_before_v4() {
+        let refuel = ActionType::Refuel { count: Some(5) };
+        assert_eq!(
+            encode(refuel.clone(), 3),
+            ActionType::Slot {
+                name: "Refuel".to_string(),
+                args: vec![5],
+            }
+        );
+        assert_eq!(encode(refuel.clone(), 4), refuel);
+    }
+
+    #[test]
+    fn handshakes_without_a_version_are_v1() {
+        let handshake: models::Handshake =
+            serde_json::from_str(r#"{"id": 4, "label": null, "kind": "Normal"}"#).unwrap();
+        assert_eq!(handshake.version, 1);
+        assert!(supported(handshake.version));
+        assert!(!supported(PROTOCOL_VERSION + 1));
+    }
+
+    #[test]
+    fn replies_without_an_id_are_read_in_order_for_v1() {
+        let reply: models::InfoResp = serde_json::from_str(
+            r#"{"chest": null, "fuel": 10, "slots": [], "blocks": [],
+                "pos": {"x": 1, "y": 2, "z": 3}}"#,
+        )
+        .unwrap();
+        assert_eq!(reply.id, 0);
+        assert!(!echoes_ids(1));
+        assert!(echoes_ids(2));
+    }
+}
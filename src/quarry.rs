@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use models::{Position, QuarryOrder, QueuedAction};
+
+use crate::world::bounds;
+
+/// Number of blocks in the box spanned by `a` and `b`.
+pub fn volume(a: Position, b: Position) -> u64 {
+    let (min, max) = bounds(a, b);
+    ((max.x - min.x + 1) * (max.y - min.y + 1) * (max.z - min.z + 1)) as u64
+}
+
+/// Plans a quarry of every block between the corners `a` and `b`, given in
+/// any order.
+///
+/// Layers are cut three at a time: the turtle snakes through the middle
+/// layer of each pass, digging the blocks above and below it as it goes. A
+/// final pass of one or two layers is cut from the layer nearest the
+/// previous pass. Every other pass runs backwards so the turtle continues
+/// from where the last one ended.
+pub fn plan(a: Position, b: Position, order: QuarryOrder) -> VecDeque<QueuedAction> {
+    let (min, max) = bounds(a, b);
+    let layers: Vec<i64> = match order {
+        QuarryOrder::TopDown => (min.y..=max.y).rev().collect(),
+        QuarryOrder::BottomUp => (min.y..=max.y).collect(),
+    };
+
+    let mut actions = VecDeque::new();
+    let mut backwards = false;
+    for pass in layers.chunks(3) {
+        let lo = *pass.iter().min().unwrap();
+        let hi = *pass.iter().max().unwrap();
+        let y = if pass.len() == 3 { lo + 1 } else { pass[0] };
+
+        let mut cells = snake(min, max, y);
+        if backwards {
+            cells.reverse();
+        }
+        backwards = !backwards;
+
+        actions.extend(cells.into_iter().map(|pos| QueuedAction::DigColumn {
+            pos,
+            up: hi > y,
+            down: lo < y,
+        }));
+    }
+
+    actions
+}
+
+/// Splits the box spanned by `a` and `b` into at most `parts` boxes of
+/// near equal size that do not overlap, cutting along the longer horizontal
+/// axis. Each part keeps the full height.
+pub fn split(a: Position, b: Position, parts: usize) -> Vec<(Position, Position)> {
+    let (min, max) = bounds(a, b);
+    let along_x = max.x - min.x >= max.z - min.z;
+    let (lo, hi) = if along_x {
+        (min.x, max.x)
+    } else {
+        (min.z, max.z)
+    };
+
+    let len = hi - lo + 1;
+    let parts = (parts.max(1) as i64).min(len);
+    let mut start = lo;
+    (0..parts)
+        .map(|i| {
+            // spread the remainder over the first parts
+            let size = len / parts + i64::from(i < len % parts);
+            let end = start + size - 1;
+            let part = if along_x {
+                (Position { x: start, ..min }, Position { x: end, ..max })
+            } else {
+                (Position { z: start, ..min }, Position { z: end, ..max })
+            };
+            start = end + 1;
+            part
+        })
+        .collect()
+}
+
+/// Every cell of layer `y`, row by row along x, alternating direction.
+fn snake(min: Position, max: Position, y: i64) -> Vec<Position> {
+    let mut cells = Vec::new();
+    for (row, z) in (min.z..=max.z).enumerate() {
+        let xs: Vec<i64> = if row % 2 == 0 {
+            (min.x..=max.x).collect()
+        } else {
+            (min.x..=max.x).rev().collect()
+        };
+        cells.extend(xs.into_iter().map(|x| Position { x, y, z }));
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn pos(x: i64, y: i64, z: i64) -> Position {
+        Position { x, y, z }
+    }
+
+    /// How often each block is dug by `plan`.
+    fn dug(plan: &VecDeque<QueuedAction>) -> HashMap<Position, u32> {
+        let mut dug = HashMap::new();
+        for action in plan {
+            let QueuedAction::DigColumn { pos, up, down } = *action else {
+                panic!("unexpected {:?}", action);
+            };
+            *dug.entry(pos).or_insert(0) += 1;
+            if up {
+                *dug.entry(pos.offset(0, 1, 0)).or_insert(0) += 1;
+            }
+            if down {
+                *dug.entry(pos.offset(0, -1, 0)).or_insert(0) += 1;
+            }
+        }
+        dug
+    }
+
+    fn column(action: &QueuedAction) -> (Position, bool, bool) {
+        match *action {
+            QueuedAction::DigColumn { pos, up, down } => (pos, up, down),
+            _ => panic!("unexpected {:?}", action),
+        }
+    }
+
+    #[test]
+    fn volume_takes_corners_in_any_order() {
+        assert_eq!(volume(pos(0, 0, 0), pos(2, 4, 1)), 30);
+        assert_eq!(volume(pos(2, 4, 1), pos(0, 0, 0)), 30);
+        assert_eq!(volume(pos(5, 5, 5), pos(5, 5, 5)), 1);
+    }
+
+    #[test]
+    fn snake_turns_back_on_every_row() {
+        let cells: Vec<_> = snake(pos(0, 7, 0), pos(2, 7, 1), 7)
+            .iter()
+            .map(|p| (p.x, p.z))
+            .collect();
+
+        assert_eq!(cells, vec![(0, 0), (1, 0), (2, 0), (2, 1), (1, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn every_block_is_dug_once_at_any_height() {
+        for height in 1..=7 {
+            for order in [QuarryOrder::TopDown, QuarryOrder::BottomUp] {
+                let (a, b) = (pos(0, 60, 0), pos(2, 60 + height - 1, 3));
+
+                let dug = dug(&plan(a, b, order));
+
+                assert_eq!(dug.len() as u64, volume(a, b), "height {}", height);
+                assert!(dug.values().all(|n| *n == 1), "height {}", height);
+            }
+        }
+    }
+
+    #[test]
+    fn a_short_last_pass_starts_next_to_the_one_before() {
+        // five layers: one pass of three, then one of two
+        let plan = plan(pos(0, 60, 0), pos(1, 64, 1), QuarryOrder::TopDown);
+        let columns: Vec<_> = plan.iter().map(column).collect();
+
+        assert_eq!(columns.len(), 8);
+        assert!(columns[..4]
+            .iter()
+            .all(|(p, up, down)| p.y == 63 && *up && *down));
+        assert!(columns[4..]
+            .iter()
+            .all(|(p, up, down)| p.y == 61 && !*up && *down));
+        // the second pass runs backwards from where the first one ended
+        assert_eq!(
+            (columns[3].0.x, columns[3].0.z),
+            (columns[4].0.x, columns[4].0.z)
+        );
+    }
+
+    #[test]
+    fn a_single_layer_is_dug_without_reaching_up_or_down() {
+        let plan = plan(pos(0, 60, 0), pos(1, 60, 0), QuarryOrder::BottomUp);
+
+        assert_eq!(
+            plan.iter().map(column).collect::<Vec<_>>(),
+            vec![(pos(0, 60, 0), false, false), (pos(1, 60, 0), false, false)]
+        );
+    }
+}
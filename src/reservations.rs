@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+
+use models::Position;
+
+/// Outcome of asking for a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// The cell is reserved for the caller, it may move in.
+    Granted,
+    /// Another turtle holds the cell, try again shortly.
+    Wait(usize),
+    /// The caller is part of a deadlock and has the lowest priority; it
+    /// must give up the cell and plan around.
+    Yield(usize),
+}
+
+/// Cells held by turtles: the one each turtle stands in and the one it is
+/// about to step into. Turtles claim a cell before moving so that two of
+/// them never try to enter, or dig into, the same cell.
+///
+/// A turtle waiting for a cell is recorded in a waits-for graph. When the
+/// graph has a cycle the turtle with the highest id in it yields, so lower
+/// ids have priority.
+#[derive(Debug, Default)]
+pub struct Reservations {
+    occupied: HashMap<usize, Position>,
+    claims: HashMap<usize, Position>,
+    waiting: HashMap<usize, usize>,
+}
+
+impl Reservations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The turtle other than `id` holding `pos`, if any.
+    fn holder(&self, id: usize, pos: Position) -> Option<usize> {
+        self.occupied
+            .iter()
+            .chain(self.claims.iter())
+            .find(|(t, p)| **t != id && **p == pos)
+            .map(|(t, _)| *t)
+    }
+
+    /// Turtles in the waits-for cycle through `id`, or empty if there is none.
+    fn cycle(&self, id: usize) -> Vec<usize> {
+        let mut members = vec![id];
+        let mut seen = HashSet::from([id]);
+        let mut current = id;
+        while let Some(next) = self.waiting.get(&current).copied() {
+            if next == id {
+                return members;
+            }
+            if !seen.insert(next) {
+                // a cycle that does not pass through `id`
+                break;
+            }
+            members.push(next);
+            current = next;
+        }
+
+        vec![]
+    }
+
+    /// Asks for `pos` on behalf of turtle `id`, replacing any earlier claim.
+    pub fn claim(&mut self, id: usize, pos: Position) -> Claim {
+        let Some(holder) = self.holder(id, pos) else {
+            self.waiting.remove(&id);
+            self.claims.insert(id, pos);
+            return Claim::Granted;
+        };
+
+        self.claims.remove(&id);
+        self.waiting.insert(id, holder);
+        let cycle = self.cycle(id);
+        if cycle.iter().max() == Some(&id) {
+            tracing::warn!("turtle {} yields to break deadlock {:?}", id, cycle);
+            self.waiting.remove(&id);
+            return Claim::Yield(holder);
+        }
+
+        Claim::Wait(holder)
+    }
+
+    /// Drops the claim of `id` and stops it waiting.
+    pub fn unclaim(&mut self, id: usize) {
+        self.claims.remove(&id);
+        self.waiting.remove(&id);
+    }
+
+    /// Records that `id` stands in `pos`. A claim on `pos` is fulfilled.
+    pub fn occupy(&mut self, id: usize, pos: Position) {
+        self.occupied.insert(id, pos);
+        if self.claims.get(&id) == Some(&pos) {
+            self.claims.remove(&id);
+        }
+    }
+
+    /// Forgets the claims of a turtle that went offline. Its cell stays
+    /// occupied: the turtle is still there even if nothing drives it.
+    pub fn leave(&mut self, id: usize) {
+        self.unclaim(id);
+        self.waiting.retain(|_, holder| *holder != id);
+    }
+
+    /// Cells held by turtles other than `id`.
+    pub fn blocked_for(&self, id: usize) -> HashSet<Position> {
+        self.occupied
+            .iter()
+            .chain(self.claims.iter())
+            .filter(|(t, _)| **t != id)
+            .map(|(_, p)| *p)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: i64) -> Position {
+        Position { x, y: 64, z: 0 }
+    }
+
+    /// Turtles 1 to 3 standing in cells 1 to 3, and turtle 4 in cell 4
+    /// about to step into cell 5.
+    fn fleet() -> Reservations {
+        let mut reservations = Reservations::new();
+        for id in 1..=4 {
+            reservations.occupy(id, cell(id as i64));
+        }
+        reservations.claim(4, cell(5));
+        reservations
+    }
+
+    #[test]
+    fn claims_on_free_and_held_cells() {
+        let cases = [
+            (1, 9, Claim::Granted),
+            (1, 1, Claim::Granted),
+            (1, 2, Claim::Wait(2)),
+            (1, 5, Claim::Wait(4)),
+            (4, 5, Claim::Granted),
+        ];
+
+        for (id, x, expected) in cases {
+            assert_eq!(
+                fleet().claim(id, cell(x)),
+                expected,
+                "turtle {} claiming cell {}",
+                id,
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn the_highest_id_in_a_cycle_yields() {
+        let cases: [&[(usize, i64, Claim)]; 4] = [
+            // two turtles swapping cells
+            &[(1, 2, Claim::Wait(2)), (2, 1, Claim::Yield(1))],
+            &[
+                (2, 1, Claim::Wait(1)),
+                (1, 2, Claim::Wait(2)),
+                (2, 1, Claim::Yield(1)),
+            ],
+            // three turtles each stepping into the next one's cell
+            &[
+                (1, 2, Claim::Wait(2)),
+                (2, 3, Claim::Wait(3)),
+                (3, 1, Claim::Yield(1)),
+            ],
+            &[
+                (3, 1, Claim::Wait(1)),
+                (1, 2, Claim::Wait(2)),
+                (2, 3, Claim::Wait(3)),
+                (3, 1, Claim::Yield(1)),
+            ],
+        ];
+
+        for steps in cases {
+            let mut reservations = fleet();
+            for (id, x, expected) in steps {
+                assert_eq!(
+                    reservations.claim(*id, cell(*x)),
+                    *expected,
+                    "turtle {} claiming cell {} in {:?}",
+                    id,
+                    x,
+                    steps
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_yielded_turtle_stops_waiting() {
+        let mut reservations = fleet();
+        reservations.claim(1, cell(2));
+        assert_eq!(reservations.claim(2, cell(1)), Claim::Yield(1));
+
+        // turtle 2 plans around, so turtle 1 is no longer in a cycle
+        assert_eq!(reservations.claim(2, cell(9)), Claim::Granted);
+        assert_eq!(reservations.claim(1, cell(2)), Claim::Wait(2));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use models::{Position, QueuedAction, Rotation, Schematic};
+
+use crate::world::AIR;
+
+/// Stacks a turtle restocks at once. The other slots stay free for fuel
+/// and for whatever it digs out of the way.
+const RESTOCK_STACKS: i64 = 12;
+const STACK_SIZE: i64 = 64;
+
+/// The item that places `block`. Block states are dropped, so
+/// `minecraft:oak_stairs[facing=east]` is placed from `minecraft:oak_stairs`
+/// and faces wherever the turtle does.
+pub fn item_for(block: &str) -> &str {
+    block.split('[').next().unwrap_or(block)
+}
+
+/// Turns an `(x, z)` offset clockwise around the origin.
+pub fn rotate(x: i64, z: i64, rotation: Rotation) -> (i64, i64) {
+    match rotation {
+        Rotation::None => (x, z),
+        Rotation::Clockwise90 => (-z, x),
+        Rotation::Clockwise180 => (-x, -z),
+        Rotation::Clockwise270 => (z, -x),
+    }
+}
+
+/// Every block to place, as a world position and item. Layers go bottom
+/// up and each layer is laid in rows that turn back on each other, so the
+/// turtle placing from above only ever moves over open air.
+pub fn placements(
+    schematic: &Schematic,
+    origin: Position,
+    rotation: Rotation,
+) -> Vec<(Position, String)> {
+    let mut placements: Vec<(Position, String)> = schematic
+        .blocks
+        .iter()
+        .filter_map(|b| {
+            let block = schematic.palette.get(b.block)?;
+            if block == AIR {
+                return None;
+            }
+
+            let (x, z) = rotate(b.x, b.z, rotation);
+            Some((origin.offset(x, b.y, z), item_for(block).to_string()))
+        })
+        .collect();
+
+    placements.sort_by_key(|(p, _)| {
+        let x = if p.z.rem_euclid(2) == 0 { p.x } else { -p.x };
+        (p.y, p.z, x)
+    });
+    placements
+}
+
+/// Items needed for the whole structure.
+pub fn materials(schematic: &Schematic) -> BTreeMap<String, i64> {
+    let mut materials = BTreeMap::new();
+    for block in &schematic.blocks {
+        match schematic.palette.get(block.block) {
+            Some(name) if name != AIR => {
+                *materials.entry(item_for(name).to_string()).or_insert(0) += 1
+            }
+            _ => {}
+        }
+    }
+    materials
+}
+
+fn stacks(batch: &BTreeMap<String, i64>) -> i64 {
+    batch
+        .values()
+        .map(|c| (c + STACK_SIZE - 1) / STACK_SIZE)
+        .sum()
+}
+
+/// The build as job actions: the placements in order, with a restock
+/// before each run of placements that fits in `RESTOCK_STACKS`.
+pub fn plan(schematic: &Schematic, origin: Position, rotation: Rotation) -> VecDeque<QueuedAction> {
+    let mut plan = VecDeque::new();
+    let mut batch: BTreeMap<String, i64> = BTreeMap::new();
+    let mut pending = Vec::new();
+
+    let mut flush = |batch: &mut BTreeMap<String, i64>, pending: &mut Vec<QueuedAction>| {
+        if !pending.is_empty() {
+            plan.push_back(QueuedAction::Restock(
+                std::mem::take(batch).into_iter().collect(),
+            ));
+            plan.extend(pending.drain(..));
+        }
+    };
+
+    for (pos, item) in placements(schematic, origin, rotation) {
+        let mut next = batch.clone();
+        *next.entry(item.clone()).or_insert(0) += 1;
+        if stacks(&next) > RESTOCK_STACKS {
+            flush(&mut batch, &mut pending);
+            next = BTreeMap::from([(item.clone(), 1)]);
+        }
+
+        batch = next;
+        pending.push(QueuedAction::PlaceBlock { pos, item });
+    }
+    flush(&mut batch, &mut pending);
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use models::SchematicBlock;
+
+    use super::*;
+
+    const ORIGIN: Position = Position {
+        x: 10,
+        y: 64,
+        z: 10,
+    };
+
+    fn schematic(blocks: &[(i64, i64, i64, usize)]) -> Schematic {
+        Schematic {
+            palette: vec![
+                "minecraft:cobblestone".to_string(),
+                AIR.to_string(),
+                "minecraft:oak_stairs[facing=east]".to_string(),
+            ],
+            blocks: blocks
+                .iter()
+                .map(|&(x, y, z, block)| SchematicBlock { x, y, z, block })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn rotation_turns_north_into_east() {
+        let s = schematic(&[(0, 0, -1, 0)]);
+
+        let placed = placements(&s, ORIGIN, Rotation::Clockwise90);
+
+        assert_eq!(placed[0].0, ORIGIN.offset(1, 0, 0));
+    }
+
+    #[test]
+    fn layers_are_placed_bottom_up_without_air() {
+        let s = schematic(&[(0, 1, 0, 0), (1, 0, 0, 2), (0, 0, 0, 1), (0, 0, 1, 0)]);
+
+        let placed = placements(&s, ORIGIN, Rotation::None);
+
+        assert_eq!(
+            placed,
+            vec![
+                (ORIGIN.offset(1, 0, 0), "minecraft:oak_stairs".to_string()),
+                (ORIGIN.offset(0, 0, 1), "minecraft:cobblestone".to_string()),
+                (ORIGIN.offset(0, 1, 0), "minecraft:cobblestone".to_string()),
+            ]
+        );
+        assert_eq!(materials(&s)["minecraft:cobblestone"], 2);
+    }
+
+    #[test]
+    fn restocks_before_the_inventory_would_overflow() {
+        let blocks: Vec<_> = (0..RESTOCK_STACKS * STACK_SIZE + 1)
+            .map(|i| (i % 32, i / 1024, (i / 32) % 32, 0))
+            .collect();
+
+        let plan = plan(&schematic(&blocks), ORIGIN, Rotation::None);
+
+        let restocks: Vec<_> = plan
+            .iter()
+            .filter_map(|a| match a {
+                QueuedAction::Restock(items) => Some(items.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            restocks,
+            vec![
+                vec![(
+                    "minecraft:cobblestone".to_string(),
+                    RESTOCK_STACKS * STACK_SIZE
+                )],
+                vec![("minecraft:cobblestone".to_string(), 1)],
+            ]
+        );
+    }
+}
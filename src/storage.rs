@@ -0,0 +1,381 @@
+use std::collections::VecDeque;
+
+use models::{
+    Chest, ChestContents, Direction, Goal, Handshake, Job, JobKind, JobStatus, Position, Progress,
+    QueuedAction, Turtle, PROTOCOL_VERSION,
+};
+use sqlx::{postgres::PgPoolOptions, types::Json, PgPool, Row};
+
+use crate::world::{Cell, CellState};
+
+/// Postgres backed persistence for fleet state.
+pub struct Storage {
+    pool: PgPool,
+}
+
+impl Storage {
+    /// Connects to `url` and runs any pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+        sqlx::migrate!().run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn save_turtle(&self, turtle: &Turtle) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO turtles (id, label, kind, x, y, z, direction, fuel, curr_goal, main_goal,
+                prev_x, prev_y, prev_z, prev_direction, action_queue, executed_actions, last_seen,
+                heading_known)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, to_timestamp($17),
+                $18)
+            ON CONFLICT (id) DO UPDATE SET
+                label = EXCLUDED.label,
+                kind = EXCLUDED.kind,
+                x = EXCLUDED.x,
+                y = EXCLUDED.y,
+                z = EXCLUDED.z,
+                direction = EXCLUDED.direction,
+                fuel = EXCLUDED.fuel,
+                curr_goal = EXCLUDED.curr_goal,
+                main_goal = EXCLUDED.main_goal,
+                prev_x = EXCLUDED.prev_x,
+                prev_y = EXCLUDED.prev_y,
+                prev_z = EXCLUDED.prev_z,
+                prev_direction = EXCLUDED.prev_direction,
+                action_queue = EXCLUDED.action_queue,
+                executed_actions = EXCLUDED.executed_actions,
+                last_seen = EXCLUDED.last_seen,
+                heading_known = EXCLUDED.heading_known",
+        )
+        .bind(turtle.id as i64)
+        .bind(&turtle.label)
+        .bind(Json(turtle.kind))
+        .bind(turtle.pos.x)
+        .bind(turtle.pos.y)
+        .bind(turtle.pos.z)
+        .bind(Json(turtle.direction))
+        .bind(turtle.fuel)
+        .bind(Json(turtle.curr_goal))
+        .bind(Json(turtle.main_goal))
+        .bind(turtle.prev_pos.x)
+        .bind(turtle.prev_pos.y)
+        .bind(turtle.prev_pos.z)
+        .bind(Json(turtle.prev_dir))
+        .bind(Json(&turtle.action_queue))
+        .bind(Json(&turtle.executed_actions))
+        .bind(turtle.last_seen as f64)
+        .bind(turtle.heading_known)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Loads every saved turtle. They come back offline until they reconnect.
+    pub async fn load_turtles(&self) -> Result<Vec<Turtle>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, label, kind, x, y, z, direction, fuel, curr_goal, main_goal,
+                prev_x, prev_y, prev_z, prev_direction, action_queue, executed_actions,
+                extract(epoch from last_seen)::bigint AS last_seen, heading_known
+            FROM turtles",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut turtles = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Json(kind) = row.try_get("kind")?;
+            let mut turtle = Turtle::new(Handshake {
+                id: row.try_get::<i64, _>("id")? as usize,
+                label: row.try_get("label")?,
+                kind,
+                version: PROTOCOL_VERSION,
+            });
+
+            turtle.last_seen = row.try_get::<i64, _>("last_seen")? as u64;
+            turtle.pos = Position {
+                x: row.try_get("x")?,
+                y: row.try_get("y")?,
+                z: row.try_get("z")?,
+            };
+            turtle.direction = row.try_get::<Json<Direction>, _>("direction")?.0;
+            turtle.heading_known = row.try_get("heading_known")?;
+            turtle.fuel = row.try_get("fuel")?;
+            turtle.curr_goal = row.try_get::<Json<Goal>, _>("curr_goal")?.0;
+            turtle.main_goal = row.try_get::<Json<Goal>, _>("main_goal")?.0;
+            turtle.prev_pos = Position {
+                x: row.try_get("prev_x")?,
+                y: row.try_get("prev_y")?,
+                z: row.try_get("prev_z")?,
+            };
+            turtle.prev_dir = row.try_get::<Json<Direction>, _>("prev_direction")?.0;
+            turtle.action_queue = row
+                .try_get::<Json<VecDeque<QueuedAction>>, _>("action_queue")?
+                .0;
+            for action in row
+                .try_get::<Json<VecDeque<QueuedAction>>, _>("executed_actions")?
+                .0
+            {
+                turtle.record_executed(action);
+            }
+
+            turtles.push(turtle);
+        }
+
+        Ok(turtles)
+    }
+
+    pub async fn save_job(&self, job: &Job) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO jobs (id, owner, group_id, attempts, kind, status, done, total, skipped,
+                step, message)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (id) DO UPDATE SET
+                owner = EXCLUDED.owner,
+                attempts = EXCLUDED.attempts,
+                status = EXCLUDED.status,
+                done = EXCLUDED.done,
+                total = EXCLUDED.total,
+                skipped = EXCLUDED.skipped,
+                step = EXCLUDED.step,
+                checkpoint = NULL,
+                message = EXCLUDED.message",
+        )
+        .bind(job.id as i64)
+        .bind(job.owner.map(|o| o as i64))
+        .bind(job.group.map(|g| g as i64))
+        .bind(job.attempts as i32)
+        .bind(Json(&job.kind))
+        .bind(Json(job.status))
+        .bind(job.progress.done as i64)
+        .bind(job.progress.total as i64)
+        .bind(job.progress.skipped as i64)
+        .bind(job.step as i64)
+        .bind(&job.message)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_jobs(&self) -> Result<Vec<Job>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, owner, group_id, attempts, kind, status, done, total, skipped, step,
+                checkpoint, message
+            FROM jobs",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Job {
+                    id: row.try_get::<i64, _>("id")? as u64,
+                    owner: row.try_get::<Option<i64>, _>("owner")?.map(|o| o as usize),
+                    group: row.try_get::<Option<i64>, _>("group_id")?.map(|g| g as u64),
+                    attempts: row.try_get::<i32, _>("attempts")? as u32,
+                    kind: row.try_get::<Json<JobKind>, _>("kind")?.0,
+                    status: row.try_get::<Json<JobStatus>, _>("status")?.0,
+                    progress: Progress {
+                        done: row.try_get::<i64, _>("done")? as u64,
+                        total: row.try_get::<i64, _>("total")? as u64,
+                        skipped: row.try_get::<i64, _>("skipped")? as u64,
+                    },
+                    // only jobs saved before steps were stored have one
+                    checkpoint: row
+                        .try_get::<Option<Json<VecDeque<QueuedAction>>>, _>("checkpoint")?
+                        .map(|c| c.0)
+                        .unwrap_or_default(),
+                    step: row.try_get::<i64, _>("step")? as u64,
+                    message: row.try_get("message")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn save_cells(&self, cells: &[(Position, Cell)]) -> Result<(), sqlx::Error> {
+        if cells.is_empty() {
+            return Ok(());
+        }
+
+        let xs: Vec<i64> = cells.iter().map(|(p, _)| p.x).collect();
+        let ys: Vec<i64> = cells.iter().map(|(p, _)| p.y).collect();
+        let zs: Vec<i64> = cells.iter().map(|(p, _)| p.z).collect();
+        let blocks: Vec<Option<String>> = cells
+            .iter()
+            .map(|(_, c)| c.state.block_name().map(str::to_string))
+            .collect();
+        let observed: Vec<f64> = cells.iter().map(|(_, c)| c.observed_at as f64).collect();
+
+        sqlx::query(
+            "INSERT INTO world_blocks (x, y, z, block, observed_at)
+            SELECT x, y, z, block, to_timestamp(observed_at)
+            FROM UNNEST($1::bigint[], $2::bigint[], $3::bigint[], $4::text[], $5::float8[])
+                AS c(x, y, z, block, observed_at)
+            ON CONFLICT (x, y, z) DO UPDATE SET
+                block = EXCLUDED.block,
+                observed_at = EXCLUDED.observed_at",
+        )
+        .bind(xs)
+        .bind(ys)
+        .bind(zs)
+        .bind(blocks)
+        .bind(observed)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_cells(&self) -> Result<Vec<(Position, Cell)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT x, y, z, block, extract(epoch from observed_at)::bigint AS observed_at
+            FROM world_blocks",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok((
+                    Position {
+                        x: row.try_get("x")?,
+                        y: row.try_get("y")?,
+                        z: row.try_get("z")?,
+                    },
+                    Cell {
+                        state: CellState::from_block_name(row.try_get("block")?),
+                        observed_at: row.try_get::<i64, _>("observed_at")? as u64,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Writes changed chests, deleting the ones that are gone.
+    pub async fn save_chests(
+        &self,
+        chests: &[(Position, Option<ChestContents>)],
+    ) -> Result<(), sqlx::Error> {
+        for (pos, contents) in chests {
+            match contents {
+                Some(contents) => {
+                    sqlx::query(
+                        "INSERT INTO chests (x, y, z, contents, seen_at)
+                        VALUES ($1, $2, $3, $4, to_timestamp($5))
+                        ON CONFLICT (x, y, z) DO UPDATE SET
+                            contents = EXCLUDED.contents,
+                            seen_at = EXCLUDED.seen_at",
+                    )
+                    .bind(pos.x)
+                    .bind(pos.y)
+                    .bind(pos.z)
+                    .bind(Json(&contents.chest))
+                    .bind(contents.seen_at as f64)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                None => {
+                    sqlx::query("DELETE FROM chests WHERE x = $1 AND y = $2 AND z = $3")
+                        .bind(pos.x)
+                        .bind(pos.y)
+                        .bind(pos.z)
+                        .execute(&self.pool)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn load_chests(&self) -> Result<Vec<ChestContents>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT x, y, z, contents, extract(epoch from seen_at)::bigint AS seen_at
+            FROM chests",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ChestContents {
+                    pos: Position {
+                        x: row.try_get("x")?,
+                        y: row.try_get("y")?,
+                        z: row.try_get("z")?,
+                    },
+                    chest: row.try_get::<Json<Chest>, _>("contents")?.0,
+                    seen_at: row.try_get::<i64, _>("seen_at")? as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobBoard;
+    use models::{MoveDirection, TurtleKind};
+
+    #[tokio::test]
+    #[ignore = "needs postgres: `docker compose up -d` and set DATABASE_URL"]
+    async fn saves_and_loads_turtles() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let storage = Storage::connect(&url).await.unwrap();
+
+        let mut turtle = Turtle::new(Handshake {
+            id: 4242,
+            label: Some("miner".to_string()),
+            kind: TurtleKind::Advanced,
+            version: PROTOCOL_VERSION,
+        });
+        turtle.pos = Position {
+            x: 10,
+            y: -20,
+            z: 30,
+        };
+        turtle.direction = Direction::West;
+        turtle.fuel = 1234;
+        turtle.main_goal = Goal::Refuel;
+        turtle
+            .action_queue
+            .push_back(QueuedAction::MoveAndMine(MoveDirection::Up));
+        turtle.last_seen = 1_700_000_000;
+
+        storage.save_turtle(&turtle).await.unwrap();
+
+        let loaded = storage.load_turtles().await.unwrap();
+        let loaded = loaded.into_iter().find(|t| t.id == 4242).unwrap();
+
+        assert_eq!(loaded.label, turtle.label);
+        assert_eq!(loaded.pos, turtle.pos);
+        assert_eq!(loaded.direction, turtle.direction);
+        assert_eq!(loaded.fuel, turtle.fuel);
+        assert_eq!(loaded.main_goal, turtle.main_goal);
+        assert_eq!(loaded.action_queue, turtle.action_queue);
+        assert_eq!(loaded.last_seen, turtle.last_seen);
+        assert!(!loaded.online);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs postgres: `docker compose up -d` and set DATABASE_URL"]
+    async fn saves_and_loads_job_progress() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let storage = Storage::connect(&url).await.unwrap();
+
+        let mut job = JobBoard::new().create(Some(1), JobKind::Deposit, None);
+        job.id = 4242;
+        job.progress.done = 7;
+        job.progress.skipped = 3;
+
+        storage.save_job(&job).await.unwrap();
+
+        let loaded = storage.load_jobs().await.unwrap();
+        let loaded = loaded.into_iter().find(|j| j.id == 4242).unwrap();
+
+        assert_eq!(loaded.progress, job.progress);
+    }
+}
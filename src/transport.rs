@@ -0,0 +1,167 @@
+use axum::extract::ws::Message;
+use futures::future::BoxFuture;
+use models::TurtleError;
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+
+/// The connection a `TurtleController` talks to its turtle over. Actions go
+/// out and replies come back as JSON text.
+pub trait Transport: Send + Sync + std::fmt::Debug {
+    fn send(&mut self, packet: String) -> Result<(), TurtleError>;
+
+    /// The next message from the turtle, or `None` once the connection is
+    /// gone.
+    fn recv(&mut self) -> BoxFuture<'_, Option<String>>;
+}
+
+/// A turtle connected over the `/ws` websocket. The socket itself is driven
+/// by `handle_connection`, this only holds the channels to it.
+#[derive(Debug)]
+pub struct WebsocketTransport {
+    sender: UnboundedSender<Message>,
+    receiver: Receiver<String>,
+}
+
+impl WebsocketTransport {
+    pub fn new(sender: UnboundedSender<Message>, receiver: Receiver<String>) -> Self {
+        Self { sender, receiver }
+    }
+}
+
+impl Transport for WebsocketTransport {
+    fn send(&mut self, packet: String) -> Result<(), TurtleError> {
+        if self.sender.send(Message::Text(packet)).is_err() {
+            tracing::warn!("could not send to the turtle, the websocket is closed");
+            return Err(TurtleError::ErrorWebsockets);
+        }
+
+        Ok(())
+    }
+
+    fn recv(&mut self) -> BoxFuture<'_, Option<String>> {
+        Box::pin(self.receiver.recv())
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use futures::future::BoxFuture;
+    use models::{
+        Action, ActionType, Handshake, InfoResp, MineDirection, MoveDirection, Position,
+        TurnDirection, Turtle, TurtleError, TurtleKind, PROTOCOL_VERSION,
+    };
+
+    use super::Transport;
+    use crate::chests::ChestIndex;
+    use crate::config::Config;
+    use crate::models::TurtleController;
+    use crate::reservations::Reservations;
+    use crate::world::WorldMap;
+
+    /// A turtle that expects exactly the actions in its script, in order,
+    /// and answers each with the scripted reply. Any other action fails the
+    /// test. Once the script is used up the connection counts as closed.
+    #[derive(Debug, Clone, Default)]
+    pub struct ScriptedTransport {
+        script: Arc<Mutex<VecDeque<(ActionType, InfoResp)>>>,
+        replies: VecDeque<InfoResp>,
+    }
+
+    impl ScriptedTransport {
+        pub fn new(script: Vec<(ActionType, InfoResp)>) -> Self {
+            Self {
+                script: Arc::new(Mutex::new(script.into())),
+                replies: VecDeque::new(),
+            }
+        }
+
+        /// Steps of the script that have not run yet.
+        pub fn remaining(&self) -> usize {
+            self.script.lock().unwrap().len()
+        }
+    }
+
+    impl Transport for ScriptedTransport {
+        fn send(&mut self, packet: String) -> Result<(), TurtleError> {
+            let action: Action = serde_json::from_str(&packet).expect("controller sent an action");
+            let Some((expected, mut reply)) = self.script.lock().unwrap().pop_front() else {
+                return Err(TurtleError::ErrorWebsockets);
+            };
+            assert_eq!(action.action, expected, "unexpected action");
+
+            reply.id = action.id;
+            self.replies.push_back(reply);
+            Ok(())
+        }
+
+        fn recv(&mut self) -> BoxFuture<'_, Option<String>> {
+            let reply = self
+                .replies
+                .pop_front()
+                .map(|r| serde_json::to_string(&r).expect("reply serializes"));
+            Box::pin(async move { reply })
+        }
+    }
+
+    /// A controller for turtle 1, fresh at the origin with default settings,
+    /// talking to a `ScriptedTransport` running `script`.
+    pub fn controller(
+        script: Vec<(ActionType, InfoResp)>,
+    ) -> (TurtleController, ScriptedTransport) {
+        let transport = ScriptedTransport::new(script);
+        let turtle = Turtle::new(Handshake {
+            id: 1,
+            label: None,
+            kind: TurtleKind::Normal,
+            version: PROTOCOL_VERSION,
+        });
+        let controller = TurtleController::new(
+            Arc::new(tokio::sync::Mutex::new(turtle)),
+            Arc::new(tokio::sync::Mutex::new(WorldMap::new())),
+            Arc::new(tokio::sync::Mutex::new(Reservations::new())),
+            Arc::new(tokio::sync::Mutex::new(ChestIndex::new())),
+            Arc::new(Config::default()),
+            Box::new(transport.clone()),
+        );
+
+        (controller, transport)
+    }
+
+    /// A reply with nothing in view and an empty inventory.
+    pub fn reply(pos: Position, fuel: i64) -> InfoResp {
+        InfoResp {
+            id: 0,
+            chest: None,
+            fuel,
+            slots: vec![],
+            blocks: vec![],
+            pos,
+            result: None,
+        }
+    }
+
+    /// What `reset_north` sends to a turtle at the origin that happens to
+    /// face north: it clears all four sides, then its first step forward
+    /// lowers z. Every reply is `reply` with the position filled in.
+    pub fn reset_north_script(reply: &InfoResp) -> Vec<(ActionType, InfoResp)> {
+        let at = |pos| InfoResp {
+            pos,
+            ..reply.clone()
+        };
+        let origin = Position { x: 0, y: 0, z: 0 };
+
+        let mut script = Vec::new();
+        for _ in 0..4 {
+            script.push((ActionType::Mine(MineDirection::Forward), at(origin)));
+            script.push((ActionType::Turn(TurnDirection::Right), at(origin)));
+        }
+        script.push((
+            ActionType::Move(MoveDirection::Forward),
+            at(origin.offset(0, 0, -1)),
+        ));
+
+        script
+    }
+}
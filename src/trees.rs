@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use models::{BlockState, Position, QueuedAction};
+
+use crate::config::TreeFarm;
+
+/// Saplings a turtle keeps for replanting when it stores its harvest.
+const SAPLINGS_KEPT: i64 = 64;
+
+/// The farm's sapling spots, row by row, each row walked the opposite way
+/// to the one before.
+pub fn spots(farm: &TreeFarm) -> Vec<Position> {
+    let mut spots = Vec::new();
+    for row in 0..farm.rows {
+        let mut columns: Vec<i64> = (0..farm.columns).collect();
+        if row % 2 == 1 {
+            columns.reverse();
+        }
+        for column in columns {
+            spots.push(
+                farm.origin
+                    .offset(column * farm.spacing, 0, row * farm.spacing),
+            );
+        }
+    }
+    spots
+}
+
+/// One round of the farm: tend every spot, burn some of the harvest for
+/// fuel if running low, store the rest and rest until the trees have grown
+/// a bit.
+pub fn plan_patrol(farm: &TreeFarm) -> VecDeque<QueuedAction> {
+    let mut actions: VecDeque<_> = spots(farm)
+        .into_iter()
+        .map(|spot| QueuedAction::TendTree {
+            spot,
+            sapling: farm.sapling.clone(),
+        })
+        .collect();
+    actions.push_back(QueuedAction::BurnFuel);
+    actions.push_back(QueuedAction::Store {
+        chest: farm.chest,
+        keep: vec![(farm.sapling.clone(), SAPLINGS_KEPT)],
+    });
+    actions.push_back(QueuedAction::Wait(farm.patrol_interval));
+    actions
+}
+
+pub fn is_log(block: &BlockState) -> bool {
+    block.tags.iter().any(|t| t == "minecraft:logs") || block.name.ends_with("_log")
+}
+
+pub fn is_sapling(block: &BlockState) -> bool {
+    block.tags.iter().any(|t| t == "minecraft:saplings") || block.name.ends_with("_sapling")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spots_snake_through_the_grid() {
+        let farm = TreeFarm {
+            origin: Position { x: 0, y: 64, z: 0 },
+            rows: 2,
+            columns: 2,
+            spacing: 3,
+            sapling: "minecraft:oak_sapling".to_string(),
+            chest: Position { x: -2, y: 63, z: 0 },
+            patrol_interval: 60,
+        };
+
+        let xz: Vec<_> = spots(&farm).iter().map(|p| (p.x, p.z)).collect();
+
+        assert_eq!(xz, vec![(0, 0), (3, 0), (3, 3), (0, 3)]);
+        assert_eq!(plan_patrol(&farm).len(), 4 + 3);
+    }
+}
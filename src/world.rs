@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+
+use models::{Block, Direction, MineDirection, Position};
+
+use crate::models::unix_time;
+
+pub const AIR: &str = "minecraft:air";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CellState {
+    /// Never observed, or observed without a block name.
+    Unknown,
+    Air,
+    Block(String),
+}
+
+impl CellState {
+    /// Name used when the cell is stored, `None` for unknown cells.
+    pub fn block_name(&self) -> Option<&str> {
+        match self {
+            CellState::Unknown => None,
+            CellState::Air => Some(AIR),
+            CellState::Block(name) => Some(name),
+        }
+    }
+
+    pub fn from_block_name(name: Option<String>) -> Self {
+        match name {
+            None => CellState::Unknown,
+            Some(name) if name == AIR => CellState::Air,
+            Some(name) => CellState::Block(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub state: CellState,
+    /// Unix time in seconds of the last observation.
+    pub observed_at: u64,
+}
+
+/// Everything the fleet has seen of the world, keyed by absolute position.
+#[derive(Debug, Default)]
+pub struct WorldMap {
+    cells: HashMap<Position, Cell>,
+    dirty: HashSet<Position>,
+}
+
+impl WorldMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the blocks a turtle at `pos` facing `facing` reported. The
+    /// turtle's own cell is recorded as air. Forward observations are skipped
+    /// when the heading is not known yet.
+    pub fn observe(&mut self, pos: Position, facing: Option<Direction>, blocks: &[Block]) {
+        let now = unix_time();
+        self.insert(pos, CellState::Air, now);
+
+        for block in blocks {
+            let target = match (block.direction, facing) {
+                (MineDirection::Forward, Some(facing)) => pos.step(facing),
+                (MineDirection::Forward, None) => continue,
+                (MineDirection::Up, _) => pos.offset(0, 1, 0),
+                (MineDirection::Down, _) => pos.offset(0, -1, 0),
+            };
+
+            let state = if block.exists {
+                CellState::from_block_name(block.block.clone())
+            } else {
+                CellState::Air
+            };
+            self.insert(target, state, now);
+        }
+    }
+
+    pub fn set(&mut self, pos: Position, state: CellState) {
+        self.insert(pos, state, unix_time());
+    }
+
+    /// Restores a cell loaded from storage without marking it dirty.
+    pub fn load(&mut self, pos: Position, cell: Cell) {
+        self.cells.insert(pos, cell);
+    }
+
+    fn insert(&mut self, pos: Position, state: CellState, observed_at: u64) {
+        self.cells.insert(pos, Cell { state, observed_at });
+        self.dirty.insert(pos);
+    }
+
+    pub fn get(&self, pos: &Position) -> Option<&Cell> {
+        self.cells.get(pos)
+    }
+
+    pub fn state(&self, pos: &Position) -> CellState {
+        self.cells
+            .get(pos)
+            .map(|c| c.state.clone())
+            .unwrap_or(CellState::Unknown)
+    }
+
+    /// A copy of the cells inside the box spanned by `a` and `b`, grown by
+    /// `margin` on every side, to search without holding the map.
+    pub fn around(&self, a: Position, b: Position, margin: i64) -> WorldMap {
+        let (min, max) = bounds(a, b);
+        let cells = self
+            .region(
+                min.offset(-margin, -margin, -margin),
+                max.offset(margin, margin, margin),
+            )
+            .into_iter()
+            .map(|(p, c)| (p, c.clone()))
+            .collect();
+
+        WorldMap {
+            cells,
+            dirty: HashSet::new(),
+        }
+    }
+
+    /// Every observed cell inside the box spanned by `a` and `b`.
+    pub fn region(&self, a: Position, b: Position) -> Vec<(Position, &Cell)> {
+        let (min, max) = bounds(a, b);
+        self.cells
+            .iter()
+            .filter(|(p, _)| {
+                (min.x..=max.x).contains(&p.x)
+                    && (min.y..=max.y).contains(&p.y)
+                    && (min.z..=max.z).contains(&p.z)
+            })
+            .map(|(p, c)| (*p, c))
+            .collect()
+    }
+
+    /// Observed cells holding a block whose name matches `pred`.
+    pub fn find<F: Fn(&str) -> bool>(&self, pred: F) -> Vec<(Position, &Cell)> {
+        self.cells
+            .iter()
+            .filter(|(_, c)| matches!(&c.state, CellState::Block(name) if pred(name)))
+            .map(|(p, c)| (*p, c))
+            .collect()
+    }
+
+    /// Cells changed since the last call, for writing to storage.
+    pub fn take_dirty(&mut self) -> Vec<(Position, Cell)> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty
+            .into_iter()
+            .filter_map(|p| self.cells.get(&p).map(|c| (p, c.clone())))
+            .collect()
+    }
+}
+
+/// Normalises two corners into (min, max) corners.
+pub fn bounds(a: Position, b: Position) -> (Position, Position) {
+    (
+        Position {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+            z: a.z.min(b.z),
+        },
+        Position {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+            z: a.z.max(b.z),
+        },
+    )
+}
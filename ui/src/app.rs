@@ -62,19 +62,7 @@ fn HomePage() -> impl IntoView {
     // let (rows, set_rows) = create_signal(VecDeque::<Vec<String>>::with_capacity(50));
     let (rows, set_rows) = create_signal(Vec::<models::Turtle>::new());
 
-    let send_message = store_value(move |_| {
-        let action = models::QueuedAction::MovePoint(Position {
-            x: -3700,
-            y: 90,
-            z: 1035,
-        });
-        let packet = serde_json::to_string(&TurtleCommand {
-            turtle_id: 1,
-            action,
-        })
-        .unwrap();
-        send(&packet);
-    });
+    let send = store_value(send);
 
     let r = create_memo(move |_| {
         let m = message.get();
@@ -106,22 +94,42 @@ fn HomePage() -> impl IntoView {
                     .into_iter()
                     .enumerate()
                     .map(|(idx, t)| {
+                        let turtle_id = t.id;
+                        let send_message = move |_| {
+                            let action = models::QueuedAction::MovePoint(Position {
+                                x: -3700,
+                                y: 90,
+                                z: 1035,
+                            });
+                            let packet = serde_json::to_string(&TurtleCommand {
+                                turtle_id,
+                                action,
+                            })
+                            .unwrap();
+                            send.with_value(|send| send(&packet));
+                        };
+                        let label = t.label.clone().unwrap_or_default();
+
                         view! {
                             <div class="text-white p-8 mt-4 rounded-2xl border">
                                 <div class="flex items-center space-x-2">
-                                    <h1 class="text-2xl font-bold">"Turtle #" {t.id}<span class="ml-2 font-mono bg-neutral-900 p-2 rounded-lg">{format!("{:?}", t.curr_goal)}</span></h1>
+                                    <h1 class="text-2xl font-bold">"Turtle #" {t.id} " " {label}<span class="ml-2 font-mono bg-neutral-900 p-2 rounded-lg">{format!("{:?}", t.curr_goal)}</span></h1>
+                                    <span class="font-mono text-xs uppercase" class:text-green-200=t.online class:text-red-300=!t.online>{if t.online { "online" } else { "offline" }}</span>
                                 </div>
                                 <div class="flex">
                                     <div class="mt-2 w-[250px] space-y-1 text-xs">
+                                        <div>"Type: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{format!("{:?}", t.kind)}</span></div>
                                         <div>"Direction: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{format!("{:?}", t.direction)}</span></div>
                                         <div>"X: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.pos.x}</span></div>
                                         <div>"Y: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.pos.y}</span></div>
                                         <div>"Z: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.pos.z}</span></div>
                                         <div>"Fuel: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.fuel}</span></div>
+                                        <div>"Quarry: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.progress.map(|p| match p.skipped { 0 => format!("{}/{}", p.done, p.total), skipped => format!("{}/{} ({} skipped)", p.done, p.total, skipped) }).unwrap_or_else(|| "-".to_string())}</span></div>
+                                        <div>"Ores found: "<span class="py-1 px-2 bg-neutral-900 font-mono rounded-md">{t.ores_found.len()}</span></div>
                                     </div>
                                     <div class="flex justify-end w-full">
                                         // <Select />
-                                        <button on:click=send_message.get_value() class="inline-flex items-center justify-center whitespace-nowrap rounded-md text-sm font-medium transition-colors focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-ring disabled:pointer-events-none disabled:opacity-50 bg-secondary text-secondary-foreground shadow-sm hover:bg-secondary/80 h-9 px-4 py-2">Move Turtle</button>
+                                        <button on:click=send_message class="inline-flex items-center justify-center whitespace-nowrap rounded-md text-sm font-medium transition-colors focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-ring disabled:pointer-events-none disabled:opacity-50 bg-secondary text-secondary-foreground shadow-sm hover:bg-secondary/80 h-9 px-4 py-2">Move Turtle</button>
                                     </div>
                                 </div>
                                 <div class="max-h-54 overflow-scroll">